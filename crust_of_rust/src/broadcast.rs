@@ -0,0 +1,435 @@
+// A broadcast channel is the fan out version of the regular one. In channels every message goes to exactly one receiver, here every receiver
+// gets its own copy of every message, hence T: Clone.
+//
+// The messages sit in a ring buffer of a fixed capacity and every receiver remembers how far it has read. Senders never wait for anyone,
+// once the buffer is full the oldest message is overwritten. A receiver that was too slow to read it finds out with a Lagged error
+// telling it how many messages it missed, and picks up from the oldest one that's still around.
+//
+// The buffer holds every message in an Arc. Reading one under the lock is just bumping the count, the actual T::clone happens after we let go,
+// so a Clone that panics (or takes its time) never runs while the lock is held.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Condvar, Mutex},
+};
+
+use crate::{
+    channels::SendError,
+    poison::{lock, wait},
+};
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+// Same as channels, no #[derive(Clone)] cause Arc is Clone no matter what T is.
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        let mut inner = lock(&self.shared.inner);
+        inner.senders += 1;
+        drop(inner);
+
+        Sender {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut inner = lock(&self.shared.inner);
+        inner.senders -= 1;
+        let is_last_sender = inner.senders == 0;
+        drop(inner);
+
+        // Every receiver that is waiting has to find out that nothing else is coming.
+        if is_last_sender {
+            self.shared.signal_data_sent.notify_all();
+        }
+    }
+}
+
+impl<T> Sender<T> {
+    // Never blocks, a full buffer just drops its oldest message. Fails only when there is no receiver left to see it.
+    pub fn send(&mut self, data_to_send: T) -> Result<(), SendError<T>> {
+        let mut inner = lock(&self.shared.inner);
+        if inner.receivers == 0 {
+            return Err(SendError(data_to_send));
+        }
+
+        inner.buffer.push_back(Arc::new(data_to_send));
+        let overwritten = if inner.buffer.len() > self.shared.capacity {
+            inner.head += 1;
+            inner.buffer.pop_front()
+        } else {
+            None
+        };
+        drop(inner);
+        // Could be the last Arc of it, in which case T's drop runs, and that's not something to do under the lock.
+        drop(overwritten);
+
+        // Unlike channels every waiting receiver wants this one, not just one of them.
+        self.shared.signal_data_sent.notify_all();
+        Ok(())
+    }
+
+    // A new receiver that gets everything sent from now on.
+    pub fn subscribe(&self) -> Receiver<T> {
+        subscribe(&self.shared)
+    }
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    // Sequence number of the next message this receiver is going to read.
+    next: u64,
+}
+
+// A clone starts reading from wherever the original currently is. Use subscribe if you only want what's sent from now on.
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        let mut inner = lock(&self.shared.inner);
+        inner.receivers += 1;
+        drop(inner);
+
+        Receiver {
+            shared: Arc::clone(&self.shared),
+            next: self.next,
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut inner = lock(&self.shared.inner);
+        inner.receivers -= 1;
+        // Nobody is left to read what's buffered, might as well free it now instead of whenever the last sender goes.
+        let unread = if inner.receivers == 0 {
+            let count = inner.buffer.len() as u64;
+            inner.head += count;
+            std::mem::take(&mut inner.buffer)
+        } else {
+            VecDeque::new()
+        };
+        drop(inner);
+        drop(unread);
+    }
+}
+
+impl<T: Clone> Receiver<T> {
+    // Blocks until there's a message this receiver hasn't seen yet.
+    pub fn receive(&mut self) -> Result<T, ReceiveError> {
+        let mut inner = lock(&self.shared.inner);
+
+        loop {
+            match inner.read(&mut self.next) {
+                Err(TryReceiveError::Empty) => {
+                    inner = wait(&self.shared.signal_data_sent, inner);
+                }
+                Ok(data) => {
+                    drop(inner);
+                    return Ok(Arc::unwrap_or_clone(data));
+                }
+                Err(TryReceiveError::Lagged(missed)) => return Err(ReceiveError::Lagged(missed)),
+                Err(TryReceiveError::Disconnected) => return Err(ReceiveError::Disconnected),
+            }
+        }
+    }
+
+    pub fn try_receive(&mut self) -> Result<T, TryReceiveError> {
+        let inner = lock(&self.shared.inner);
+        let data = inner.read(&mut self.next);
+        drop(inner);
+        data.map(Arc::unwrap_or_clone)
+    }
+}
+
+impl<T> Receiver<T> {
+    // A new receiver that gets everything sent from now on, no matter how far behind this one is.
+    pub fn subscribe(&self) -> Receiver<T> {
+        subscribe(&self.shared)
+    }
+}
+
+fn subscribe<T>(shared: &Arc<Shared<T>>) -> Receiver<T> {
+    let mut inner = lock(&shared.inner);
+    inner.receivers += 1;
+    let next = inner.tail();
+    drop(inner);
+
+    Receiver {
+        shared: Arc::clone(shared),
+        next,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReceiveError {
+    // The receiver fell behind and this many messages were overwritten before it got to them. The next receive carries on with the oldest one left.
+    Lagged(u64),
+    // Everything has been read and all of the senders are gone.
+    Disconnected,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryReceiveError {
+    // Nothing new right now, but there are still senders around.
+    Empty,
+    // Same as ReceiveError::Lagged.
+    Lagged(u64),
+    // Everything has been read and all of the senders are gone.
+    Disconnected,
+}
+
+impl std::fmt::Display for ReceiveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReceiveError::Lagged(missed) => {
+                write!(f, "receiver lagged behind by {missed} messages")
+            }
+            ReceiveError::Disconnected => f.write_str("receiving on a disconnected channel"),
+        }
+    }
+}
+
+impl std::error::Error for ReceiveError {}
+
+impl std::fmt::Display for TryReceiveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TryReceiveError::Empty => f.write_str("receiving on an empty channel"),
+            TryReceiveError::Lagged(missed) => {
+                write!(f, "receiver lagged behind by {missed} messages")
+            }
+            TryReceiveError::Disconnected => f.write_str("receiving on a disconnected channel"),
+        }
+    }
+}
+
+impl std::error::Error for TryReceiveError {}
+
+// Every message gets a sequence number, counting up from 0. head is the one of the oldest message still in the buffer,
+// so buffer[i] is message head + i. They never wrap around, you'd need to send a u64 worth of messages for that.
+struct Inner<T> {
+    buffer: VecDeque<Arc<T>>,
+    head: u64,
+    senders: usize,
+    receivers: usize,
+}
+
+impl<T> Inner<T> {
+    // Sequence number the next message is going to get.
+    fn tail(&self) -> u64 {
+        self.head + self.buffer.len() as u64
+    }
+
+    // Reads the message a receiver is at and moves it along. Takes the receiver's position instead of the receiver itself cause the receiver
+    // is already borrowed by the guard we're called through. Hands out the Arc, the caller clones the T once it has let go of the lock.
+    fn read(&self, next: &mut u64) -> Result<Arc<T>, TryReceiveError> {
+        // What we were about to read has been overwritten. Skip ahead to the oldest message that's left and tell the caller how much it missed.
+        if *next < self.head {
+            let missed = self.head - *next;
+            *next = self.head;
+            return Err(TryReceiveError::Lagged(missed));
+        }
+
+        if *next < self.tail() {
+            let data = Arc::clone(&self.buffer[(*next - self.head) as usize]);
+            *next += 1;
+            return Ok(data);
+        }
+
+        // Only once we've read everything, messages sent before the senders left are still delivered.
+        if self.senders == 0 {
+            Err(TryReceiveError::Disconnected)
+        } else {
+            Err(TryReceiveError::Empty)
+        }
+    }
+}
+
+struct Shared<T> {
+    inner: Mutex<Inner<T>>,
+    signal_data_sent: Condvar,
+    // How many messages are kept around for slow receivers. Never changes so it's outside the Mutex.
+    capacity: usize,
+}
+
+// Its convention to return the Sender first and then the Receiver. More receivers come from subscribe.
+pub fn broadcast<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    // With no room at all every message would be overwritten the moment it's sent.
+    assert!(capacity > 0, "broadcast capacity must be at least 1");
+
+    let shared = Arc::new(Shared {
+        inner: Mutex::new(Inner {
+            buffer: VecDeque::with_capacity(capacity),
+            head: 0,
+            senders: 1,
+            receivers: 1,
+        }),
+        signal_data_sent: Condvar::new(),
+        capacity,
+    });
+
+    (
+        Sender {
+            shared: Arc::clone(&shared),
+        },
+        Receiver { shared, next: 0 },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn every_receiver_gets_every_message() {
+        let (mut sender, mut first) = broadcast(4);
+        let mut second = first.subscribe();
+
+        sender.send(1).unwrap();
+        sender.send(2).unwrap();
+
+        assert_eq!(first.receive(), Ok(1));
+        assert_eq!(first.receive(), Ok(2));
+        assert_eq!(second.receive(), Ok(1));
+        assert_eq!(second.receive(), Ok(2));
+    }
+
+    #[test]
+    fn subscribe_only_sees_later_messages() {
+        let (mut sender, mut first) = broadcast(4);
+        sender.send(1).unwrap();
+
+        let mut late = sender.subscribe();
+        sender.send(2).unwrap();
+
+        assert_eq!(late.receive(), Ok(2));
+        assert_eq!(first.receive(), Ok(1));
+        assert_eq!(first.receive(), Ok(2));
+    }
+
+    #[test]
+    fn clone_keeps_position() {
+        let (mut sender, mut receiver) = broadcast(4);
+        sender.send(1).unwrap();
+        sender.send(2).unwrap();
+        assert_eq!(receiver.receive(), Ok(1));
+
+        let mut cloned = receiver.clone();
+        assert_eq!(cloned.receive(), Ok(2));
+        assert_eq!(receiver.receive(), Ok(2));
+    }
+
+    #[test]
+    fn slow_receiver_lags() {
+        let (mut sender, mut receiver) = broadcast(2);
+        for i in 0..5 {
+            // The sender never blocks even though nobody is reading.
+            sender.send(i).unwrap();
+        }
+
+        assert_eq!(receiver.receive(), Err(ReceiveError::Lagged(3)));
+        assert_eq!(receiver.receive(), Ok(3));
+        assert_eq!(receiver.receive(), Ok(4));
+        assert_eq!(receiver.try_receive(), Err(TryReceiveError::Empty));
+    }
+
+    #[test]
+    fn lagging_receiver_does_not_affect_others() {
+        let (mut sender, mut slow) = broadcast(2);
+        let mut fast = slow.subscribe();
+
+        for i in 0..4 {
+            sender.send(i).unwrap();
+            assert_eq!(fast.receive(), Ok(i));
+        }
+
+        assert_eq!(slow.try_receive(), Err(TryReceiveError::Lagged(2)));
+        assert_eq!(slow.try_receive(), Ok(2));
+    }
+
+    #[test]
+    fn disconnected_after_reading_everything() {
+        let (mut sender, mut receiver) = broadcast(4);
+        sender.send(1).unwrap();
+        drop(sender);
+
+        assert_eq!(receiver.receive(), Ok(1));
+        assert_eq!(receiver.receive(), Err(ReceiveError::Disconnected));
+        assert_eq!(receiver.try_receive(), Err(TryReceiveError::Disconnected));
+    }
+
+    #[test]
+    fn send_fails_without_receivers() {
+        let (mut sender, receiver) = broadcast(4);
+        let other = receiver.subscribe();
+        drop(receiver);
+        assert_eq!(sender.send(1), Ok(()));
+
+        drop(other);
+        assert_eq!(sender.send(2), Err(SendError(2)));
+    }
+
+    #[test]
+    fn waiting_receivers_all_wake_up() {
+        let (mut sender, receiver) = broadcast(4);
+
+        let waiting: Vec<_> = (0..4)
+            .map(|_| {
+                let mut receiver = receiver.subscribe();
+                std::thread::spawn(move || {
+                    let mut got = Vec::new();
+                    while let Ok(data) = receiver.receive() {
+                        got.push(data);
+                    }
+                    got
+                })
+            })
+            .collect();
+
+        std::thread::sleep(Duration::from_millis(50));
+        sender.send(1).unwrap();
+        sender.send(2).unwrap();
+        drop(sender);
+
+        for t in waiting {
+            assert_eq!(t.join().unwrap(), vec![1, 2]);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_capacity_panics() {
+        let _ = broadcast::<()>(0);
+    }
+
+    #[test]
+    fn panicking_clone_leaves_the_channel_usable() {
+        #[derive(Debug, PartialEq)]
+        struct Fussy(u32);
+
+        impl Clone for Fussy {
+            fn clone(&self) -> Self {
+                assert_ne!(self.0, 0, "won't clone a zero");
+                Fussy(self.0)
+            }
+        }
+
+        let (mut sender, mut other) = broadcast(4);
+        sender.send(Fussy(0)).unwrap();
+        let mut receiver = sender.subscribe();
+        sender.send(Fussy(1)).unwrap();
+
+        // The clone panics outside of the lock, so nothing gets poisoned and dropping the receiver on the way out is fine.
+        let t = std::thread::spawn(move || other.receive());
+        assert!(t.join().is_err());
+        assert!(!sender.shared.inner.is_poisoned());
+
+        assert_eq!(receiver.receive(), Ok(Fussy(1)));
+        sender.send(Fussy(2)).unwrap();
+        assert_eq!(receiver.receive(), Ok(Fussy(2)));
+    }
+}
@@ -0,0 +1,276 @@
+// An in-process pipe. ChannelWriter turns a Sender<Vec<u8>> into an io::Write and ChannelReader turns a Receiver<Vec<u8>> into an io::Read
+// and io::BufRead, so a producer thread can feed anything that wants a reader without it ever knowing there's a channel in between.
+//
+// Every write becomes one chunk on the channel. The reader hands out the chunk it's on bit by bit and only goes to the channel for the next one
+// once it's used up, which makes BufRead free, the chunk already is the buffer. Chunk boundaries don't mean anything to the reader,
+// a line split across two writes reads just the same.
+//
+// It ends the way a pipe does. Once every writer is gone and everything they wrote has been read, the reader hits EOF.
+// Once the reader is gone, writing fails with BrokenPipe.
+
+use std::io::{self, BufRead, Read, Write};
+
+use crate::channels::{self, Receiver, Sender};
+
+pub struct ChannelWriter {
+    sender: Sender<Vec<u8>>,
+}
+
+// More than one writer is fine, each write is one chunk so writes from different threads never get mixed up mid write.
+impl Clone for ChannelWriter {
+    fn clone(&self) -> Self {
+        ChannelWriter {
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+impl ChannelWriter {
+    pub fn new(sender: Sender<Vec<u8>>) -> Self {
+        ChannelWriter { sender }
+    }
+
+    pub fn into_inner(self) -> Sender<Vec<u8>> {
+        self.sender
+    }
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Nothing to send, and an empty chunk would just be something for the reader to skip.
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        self.sender
+            .send(buf.to_vec())
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    // Every write goes straight into the channel, there's nothing held back to flush. Wrap it in a BufWriter if lots of tiny writes
+    // turning into lots of tiny chunks is a problem.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub struct ChannelReader {
+    receiver: Receiver<Vec<u8>>,
+    // The chunk we're working through and how much of it has been read already.
+    chunk: Vec<u8>,
+    position: usize,
+}
+
+impl ChannelReader {
+    pub fn new(receiver: Receiver<Vec<u8>>) -> Self {
+        ChannelReader {
+            receiver,
+            chunk: Vec::new(),
+            position: 0,
+        }
+    }
+
+    // Whatever is left of the current chunk is lost.
+    pub fn into_inner(self) -> Receiver<Vec<u8>> {
+        self.receiver
+    }
+}
+
+impl BufRead for ChannelReader {
+    // Blocks until there's something to read. An empty slice means EOF, all of the writers are gone and there's nothing left.
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        // A loop cause a chunk could be empty if somebody went around the writer and sent one on the channel directly.
+        while self.position == self.chunk.len() {
+            match self.receiver.receive() {
+                Some(chunk) => {
+                    self.chunk = chunk;
+                    self.position = 0;
+                }
+                None => return Ok(&[]),
+            }
+        }
+
+        Ok(&self.chunk[self.position..])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.position = (self.position + amount).min(self.chunk.len());
+    }
+}
+
+impl Read for ChannelReader {
+    // Like reading from a pipe, it blocks until there's at least some data and then returns whatever is at hand, which can be less than buf.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Nothing fits anyways. io::Read says that's an Ok(0) right away, waiting for a chunk we can't hand out would just hang.
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let available = self.fill_buf()?;
+        let count = available.len().min(buf.len());
+        buf[..count].copy_from_slice(&available[..count]);
+        self.consume(count);
+        Ok(count)
+    }
+}
+
+// Its convention to return the sending side first and then the receiving one. Unbounded like channels::channel, the writer never blocks.
+pub fn pipe() -> (ChannelWriter, ChannelReader) {
+    let (sender, receiver) = channels::channel();
+    (ChannelWriter::new(sender), ChannelReader::new(receiver))
+}
+
+// Same but at most capacity chunks can be waiting, after that writes block until the reader catches up. Counted in writes, not in bytes.
+pub fn sync_pipe(capacity: usize) -> (ChannelWriter, ChannelReader) {
+    let (sender, receiver) = channels::sync_channel(capacity);
+    (ChannelWriter::new(sender), ChannelReader::new(receiver))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::str_split::StrSplit;
+    use std::thread;
+
+    #[test]
+    fn read_what_was_written() {
+        let (mut writer, mut reader) = pipe();
+        writer.write_all(b"hello ").unwrap();
+        writer.write_all(b"world").unwrap();
+        drop(writer);
+
+        let mut read = String::new();
+        reader.read_to_string(&mut read).unwrap();
+        assert_eq!(read, "hello world");
+    }
+
+    #[test]
+    fn eof_once_writers_are_gone() {
+        let (writer, mut reader) = pipe();
+        let other = writer.clone();
+        drop(writer);
+
+        let t = thread::spawn(move || {
+            let mut read = Vec::new();
+            reader.read_to_end(&mut read).unwrap();
+            read
+        });
+
+        thread::sleep(std::time::Duration::from_millis(50));
+        assert!(!t.is_finished());
+        drop(other);
+        assert!(t.join().unwrap().is_empty());
+    }
+
+    #[test]
+    fn small_reads_across_chunks() {
+        let (mut writer, mut reader) = pipe();
+        writer.write_all(b"abc").unwrap();
+        writer.write_all(b"de").unwrap();
+        drop(writer);
+
+        let mut buf = [0; 2];
+        let mut reads = Vec::new();
+        loop {
+            let count = reader.read(&mut buf).unwrap();
+            if count == 0 {
+                break;
+            }
+            reads.push(buf[..count].to_vec());
+        }
+        // A read never goes past the end of a chunk.
+        assert_eq!(reads, vec![b"ab".to_vec(), b"c".to_vec(), b"de".to_vec()]);
+    }
+
+    #[test]
+    fn empty_read_doesnt_wait() {
+        let (mut writer, mut reader) = pipe();
+        assert_eq!(reader.read(&mut []).unwrap(), 0);
+
+        // And it doesn't eat anything either.
+        writer.write_all(b"data").unwrap();
+        assert_eq!(reader.read(&mut []).unwrap(), 0);
+        let mut buf = [0; 4];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"data");
+    }
+
+    #[test]
+    fn write_fails_once_reader_is_gone() {
+        let (mut writer, reader) = pipe();
+        drop(reader);
+
+        let error = writer.write(b"anyone?").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::BrokenPipe);
+        // Empty writes never touch the channel.
+        assert_eq!(writer.write(b"").unwrap(), 0);
+    }
+
+    #[test]
+    fn lines_split_across_writes() {
+        let (mut writer, reader) = pipe();
+
+        let t = thread::spawn(move || {
+            // Chunk boundaries in the middle of lines and of fields.
+            for piece in ["name,age\nali", "ce,30\nbob,", "25\n", "carol,4", "1"] {
+                writer.write_all(piece.as_bytes()).unwrap();
+            }
+        });
+
+        // A StrSplit based parser fed straight from the producer, it only ever sees whole lines.
+        let rows: Vec<Vec<String>> = reader
+            .lines()
+            .map(|line| {
+                StrSplit::new(&line.unwrap(), ",")
+                    .map(String::from)
+                    .collect()
+            })
+            .collect();
+        t.join().unwrap();
+
+        assert_eq!(
+            rows,
+            vec![
+                vec!["name", "age"],
+                vec!["alice", "30"],
+                vec!["bob", "25"],
+                vec!["carol", "41"],
+            ]
+        );
+    }
+
+    #[test]
+    fn split_whole_stream() {
+        let (mut writer, mut reader) = sync_pipe(1);
+
+        let t = thread::spawn(move || {
+            for i in 0..100 {
+                write!(writer, "{i} ").unwrap();
+            }
+        });
+
+        let mut read = String::new();
+        reader.read_to_string(&mut read).unwrap();
+        t.join().unwrap();
+
+        let numbers: Vec<u32> = StrSplit::new(read.trim_end(), " ")
+            .map(|number| number.parse().unwrap())
+            .collect();
+        assert_eq!(numbers, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn empty_chunks_are_skipped() {
+        let (mut sender, receiver) = channels::channel();
+        sender.send(Vec::new()).unwrap();
+        sender.send(b"data".to_vec()).unwrap();
+        drop(sender);
+
+        let mut read = String::new();
+        ChannelReader::new(receiver)
+            .read_to_string(&mut read)
+            .unwrap();
+        assert_eq!(read, "data");
+    }
+}
@@ -3,15 +3,28 @@
 // I'm gonna have this on all files :toll_face_emote:
 
 // Channel is simply a medium through which we can send data from one place and receive it at a different one.
-// It is multi producer, single consumer (mpsc).
-// There are many to many channes as well, I don't think the standard library has those.
+// It started out as multi producer, single consumer (mpsc) but the Receiver can be cloned as well now, so it's multi producer, multi consumer (mpmc).
+// Every message still goes to exactly one of the receivers, whichever one gets to the queue first. The standard library's mpsc doesn't do that.
 
 // This is going to use other parts of the sync module. I'll see if the rust atomics book does it any different.
 
 #![allow(unused)]
 use std::{
     collections::{VecDeque, vec_deque},
-    sync::{Arc, Condvar, Mutex},
+    future::Future,
+    pin::Pin,
+    sync::{
+        Arc, Condvar, Mutex, MutexGuard,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    task::{Context, Poll, Waker},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    poison::{lock, wait, wait_timeout},
+    receiver,
 };
 
 pub struct Sender<T> {
@@ -23,7 +36,7 @@ pub struct Sender<T> {
 // This is incorrect for our context cause Arc makes the interior value clonable irrespective of it being clone or not, and we want to keep it that way.
 impl<T> Clone for Sender<T> {
     fn clone(&self) -> Self {
-        let mut inner = self.shared.inner.lock().unwrap();
+        let mut inner = lock(&self.shared.inner);
         inner.senders += 1;
         // Same reason as others to avoid deadlocking / infinitely holding the lock.
         drop(inner);
@@ -38,81 +51,900 @@ impl<T> Clone for Sender<T> {
 
 impl<T> Drop for Sender<T> {
     fn drop(&mut self) {
-        let mut inner = self.shared.inner.lock().unwrap();
+        // A sender that goes away cause its thread is unwinding most likely didn't get to send everything it meant to.
+        // Set before we take the lock, so a receiver that finds out about the disconnect below finds out about this too.
+        if thread::panicking() {
+            self.shared.sender_panicked.store(true, Ordering::Relaxed);
+        }
+
+        let mut inner = lock(&self.shared.inner);
         inner.senders -= 1;
-        let is_last_sender = inner.senders == 1;
+        // We've already decremented, so we were the last one if nobody is left. Checking for 1 here would notify one sender too early
+        // and then never again, leaving the receiver waiting forever.
+        let is_last_sender = inner.senders == 0;
+        // A disconnected channel counts as ready for a Select and for a pending receive_async, they get a None out of it.
+        let wakers = if is_last_sender {
+            inner.notify_selectors()
+        } else {
+            Vec::new()
+        };
         // Same reason as others to avoid deadlocking / infinitely holding the lock.
         drop(inner);
+        wakers.into_iter().for_each(Waker::wake);
 
-        // Notify the receiver in case it was wistfully waiting while the last of the senders bite the dust.
+        // Notify the receivers in case they were wistfully waiting while the last of the senders bite the dust.
+        // All of them, there can be more than one now and every single one of them has to find out that nothing else is coming.
         if is_last_sender {
-            self.shared.signal_data_sent.notify_one();
+            self.shared.signal_data_sent.notify_all();
+        }
+    }
+}
+
+// Returned when there is nobody left to receive the value. We hand the value back so the caller doesn't lose it.
+#[derive(PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+impl<T> SendError<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+// Written by hand instead of derived so that it doesn't require T: Debug. Otherwise you couldn't unwrap a send of something that isn't Debug.
+impl<T> std::fmt::Debug for SendError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SendError { .. }")
+    }
+}
+
+impl<T> std::fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("sending on a disconnected channel")
+    }
+}
+
+impl<T> std::error::Error for SendError<T> {}
+
+// Returned by try_send. Either way the value comes back to the caller.
+#[derive(PartialEq, Eq)]
+pub enum TrySendError<T> {
+    // There's no room for it right now, send would have blocked.
+    Full(T),
+    // Same as SendError, nobody is left to receive it.
+    Disconnected(T),
+}
+
+impl<T> TrySendError<T> {
+    pub fn into_inner(self) -> T {
+        match self {
+            TrySendError::Full(data) | TrySendError::Disconnected(data) => data,
+        }
+    }
+}
+
+// Same reason as SendError, no T: Debug needed.
+impl<T> std::fmt::Debug for TrySendError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrySendError::Full(_) => f.write_str("Full(..)"),
+            TrySendError::Disconnected(_) => f.write_str("Disconnected(..)"),
+        }
+    }
+}
+
+impl<T> std::fmt::Display for TrySendError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrySendError::Full(_) => f.write_str("sending on a full channel"),
+            TrySendError::Disconnected(_) => f.write_str("sending on a disconnected channel"),
         }
     }
 }
 
+impl<T> std::error::Error for TrySendError<T> {}
+
 impl<T> Sender<T> {
-    pub fn send(&mut self, data_to_send: T) {
+    pub fn send(&mut self, data_to_send: T) -> Result<(), SendError<T>> {
         // You could wrap the next two lines in a block and the queue would be dropped implicitly but, we'd rather be explicit. Makes it easier to read.
-        // These things do return Poision struct which tells us if the other thread has panicked we've ignored it for now.
-        let mut inner = self.shared.inner.lock().unwrap();
-        inner.queue.push_back(data_to_send);
-        // We need to drop the queue and its held lock otherwise the other thread would wake up but never get the Mutex, likely a deadlock.
-        drop(inner);
+        // A poisoned lock is fine by us, see poison.
+        let mut inner = lock(&self.shared.inner);
+
+        // All of the receivers are gone, pushing to the queue would just pile things up that nobody is ever going to read.
+        // The waits below check this again since the receivers could go away while we're sleeping.
+        if inner.receivers == 0 {
+            return Err(SendError(data_to_send));
+        }
+
+        match self.shared.capacity {
+            // Rendezvous channel, the queue is only ever used as a single hand off slot and we don't return until the receiver has taken the value.
+            Some(0) => {
+                // Another sender might be mid hand off, wait for its value to be picked up first.
+                while !inner.queue.is_empty() {
+                    inner = wait(&self.shared.signal_space_available, inner);
+                    if inner.receivers == 0 {
+                        return Err(SendError(data_to_send));
+                    }
+                }
+
+                inner.queue.push_back(data_to_send);
+                self.shared.record_sent();
+                // The queue was empty so everything sent before us has been received already, ours is the very next one to go.
+                let ticket = inner.received + 1;
+
+                // No need to drop the lock before notifying here, the wait right after gives it up anyways.
+                let wakers = inner.notify_selectors();
+                self.shared.signal_data_sent.notify_one();
+                // Wakers are the exception, they run code we know nothing about so we let go of the lock for them.
+                // A receiver could take our value in the meantime, that's fine, the loop below checks the ticket first thing.
+                if !wakers.is_empty() {
+                    drop(inner);
+                    wakers.into_iter().for_each(Waker::wake);
+                    inner = lock(&self.shared.inner);
+                }
+                while inner.received < ticket {
+                    inner = wait(&self.shared.signal_space_available, inner);
+                    // Nobody is ever going to pick it up. Ours is the only value in the slot so we can take it right back.
+                    if inner.receivers == 0 && inner.received < ticket {
+                        let data = inner
+                            .queue
+                            .pop_back()
+                            .expect("rendezvous value is still in the slot");
+                        // It was never sent after all.
+                        self.shared.total_sent.fetch_sub(1, Ordering::Relaxed);
+                        return Err(SendError(data));
+                    }
+                }
+                return Ok(());
+            }
+            // Bounded channels make the sender wait for the receiver to catch up, that's the backpressure. Same loop + condvar dance as the receiver,
+            // just on the other condvar.
+            Some(capacity) => {
+                while inner.queue.len() >= capacity {
+                    inner = wait(&self.shared.signal_space_available, inner);
+                    if inner.receivers == 0 {
+                        return Err(SendError(data_to_send));
+                    }
+                }
+            }
+            // Unbounded channels have no capacity and skip this entirely.
+            None => {}
+        }
+
+        self.shared.push(inner, data_to_send);
+        Ok(())
+    }
+
+    // Same as send but it never waits. A full bounded channel gives the value right back instead of blocking.
+    // A rendezvous channel is always full as far as this is concerned, handing a value over means waiting for the receiver to take it.
+    pub fn try_send(&mut self, data_to_send: T) -> Result<(), TrySendError<T>> {
+        let inner = lock(&self.shared.inner);
+
+        if inner.receivers == 0 {
+            return Err(TrySendError::Disconnected(data_to_send));
+        }
+
+        match self.shared.capacity {
+            Some(capacity) if inner.queue.len() >= capacity => {
+                Err(TrySendError::Full(data_to_send))
+            }
+            _ => {
+                self.shared.push(inner, data_to_send);
+                Ok(())
+            }
+        }
+    }
+
+    // How many values were sent and not received yet. Doesn't take the lock, so with other threads around it's already old by the time you look at it.
+    pub fn len(&self) -> usize {
+        self.shared.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // None for an unbounded channel, Some(0) for a rendezvous one.
+    pub fn capacity(&self) -> Option<usize> {
+        self.shared.capacity
+    }
+
+    pub fn sender_count(&self) -> usize {
+        lock(&self.shared.inner).senders
+    }
+
+    pub fn receiver_count(&self) -> usize {
+        lock(&self.shared.inner).receivers
+    }
 
-        self.shared.signal_data_sent.notify_one();
+    // For a sender that means there's nobody left to send to, every send from here on fails.
+    pub fn is_disconnected(&self) -> bool {
+        self.receiver_count() == 0
+    }
+
+    // Every value that was successfully sent since the channel was created.
+    pub fn total_sent(&self) -> usize {
+        self.shared.total_sent.load(Ordering::Relaxed)
+    }
+
+    // Every value that was handed out by a receiver since the channel was created. Values the last receiver dropped unread count as well.
+    pub fn total_received(&self) -> usize {
+        self.shared.total_received.load(Ordering::Relaxed)
+    }
+
+    // The largest len the channel ever had.
+    pub fn high_water_mark(&self) -> usize {
+        self.shared.high_water_mark.load(Ordering::Relaxed)
     }
 }
 
-// The Reciever needs to have a mutex despite there being only one consumer is because a send and receive could happen at the same time, which would likely lead to problems.
-// To avoid those we have Mutex on both sender and receiver.
+// The Reciever needs to have a mutex because a send and receive could happen at the same time, which would likely lead to problems. Even more so now that
+// there can be many of them. To avoid those we have Mutex on both sender and receiver.
 pub struct Receiver<T> {
     shared: Arc<Shared<T>>,
+    // Values we already pulled out of the shared queue but haven't handed out yet. On an unbounded channel with a single receiver a receive that
+    // finds this empty swaps the whole shared queue in here under a single lock, so the next bunch of receives don't touch the Mutex at all.
+    // Once there are cloned receivers every receive takes just the one value, see batch_size. Whatever was already buffered before the clone
+    // stays with this receiver though.
+    buffer: VecDeque<T>,
+}
+
+// Same deal as the Sender, no #[derive(Clone)] cause we don't want to require T: Clone.
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        let mut inner = lock(&self.shared.inner);
+        inner.receivers += 1;
+        drop(inner);
+
+        // The buffer is ours alone, the clone starts with an empty one.
+        Receiver {
+            shared: Arc::clone(&self.shared),
+            buffer: VecDeque::new(),
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut inner = lock(&self.shared.inner);
+        inner.receivers -= 1;
+        let is_last_receiver = inner.receivers == 0;
+
+        // Our buffered values were taken out of the queue but never handed out. If somebody else can still read them, they go back to the front
+        // of the queue, in the same order. Otherwise they get dropped together with the Receiver.
+        let returned = if is_last_receiver {
+            0
+        } else {
+            self.buffer.len()
+        };
+        let mut wakers = Vec::new();
+        if returned > 0 {
+            while let Some(data) = self.buffer.pop_back() {
+                inner.queue.push_front(data);
+            }
+            inner.received -= returned;
+            wakers = inner.notify_selectors();
+        }
+
+        // Whatever is still queued is never going to be read, so we take it out to drop it. We drop it after releasing the lock though,
+        // T's drop could be doing anything and there's no reason to do it while blocking everybody else.
+        // Rendezvous is the exception, the value in the slot still belongs to the sender waiting on it and it's going to hand it back to its caller.
+        let unread = if is_last_receiver && self.shared.capacity != Some(0) {
+            std::mem::take(&mut inner.queue)
+        } else {
+            VecDeque::new()
+        };
+        // These are off the channel for good, as far as the counters go that's the same as being received. Otherwise len would never get back to 0.
+        let discarded = unread.len() + self.buffer.len();
+        self.shared
+            .total_received
+            .fetch_add(discarded, Ordering::Relaxed);
+        drop(inner);
+        drop(unread);
+        wakers.into_iter().for_each(Waker::wake);
+
+        // Senders blocked on a full (or rendezvous) channel would wait forever otherwise. They all need to find out, hence notify_all.
+        if is_last_receiver {
+            self.shared.signal_space_available.notify_all();
+        }
+        if returned > 0 {
+            self.shared.signal_data_sent.notify_all();
+        }
+    }
 }
 
 impl<T> Receiver<T> {
     pub fn receive(&mut self) -> Option<T> {
-        // These things do return Poision struct which tells us if the other thread has panicked we've ignored it for now.
-        let mut inner = self.shared.inner.lock().unwrap();
+        // Fast path, no lock needed for what we already have.
+        if let Some(data) = self.pop_buffer() {
+            return Some(data);
+        }
 
-        // We loop but it's not a spinlock type. The condvar makes sure that the thread sleeps when we hit None arm.
+        // A poisoned lock is fine by us, see poison.
+        let mut inner = lock(&self.shared.inner);
+
+        // We loop but it's not a spinlock type. The condvar makes sure that the thread sleeps when the queue is empty.
         // When the receiver signals the thread is woken up again.
         loop {
-            match inner.queue.pop_front() {
+            if !inner.queue.is_empty() {
                 // We retun from here so the Mutex is dropped anyways, no need for explicit mention here.
-                Some(data) => return Some(data),
-                None if inner.senders == 0 => return None,
-                None => {
-                    // wait automatically dros the Mutex so the thread that needs to wake this up can acquire the shred resource. Otherwise you guessed it, it's a deadlock.
-                    inner = self.shared.signal_data_sent.wait(inner).unwrap();
+                let batch_size = self.shared.batch_size(&inner);
+                self.shared
+                    .move_to_buffer(inner, &mut self.buffer, batch_size);
+                return self.pop_buffer();
+            }
+
+            if inner.senders == 0 {
+                return None;
+            }
+
+            // wait automatically dros the Mutex so the thread that needs to wake this up can acquire the shred resource. Otherwise you guessed it, it's a deadlock.
+            inner = wait(&self.shared.signal_data_sent, inner);
+        }
+    }
+
+    // Same as receive but it never waits. Empty means try again later, Disconnected means don't bother, nothing is ever coming.
+    pub fn try_receive(&mut self) -> Result<T, TryReceiveError> {
+        if let Some(data) = self.pop_buffer() {
+            return Ok(data);
+        }
+
+        let inner = lock(&self.shared.inner);
+
+        if !inner.queue.is_empty() {
+            let batch_size = self.shared.batch_size(&inner);
+            self.shared
+                .move_to_buffer(inner, &mut self.buffer, batch_size);
+            return self.pop_buffer().ok_or(TryReceiveError::Empty);
+        }
+
+        if inner.senders == 0 {
+            Err(TryReceiveError::Disconnected)
+        } else {
+            Err(TryReceiveError::Empty)
+        }
+    }
+
+    pub fn receive_timeout(&mut self, timeout: Duration) -> Result<T, ReceiveTimeoutError> {
+        receiver::receive_timeout(self, timeout, Self::receive, Self::receive_deadline)
+    }
+
+    pub fn receive_deadline(&mut self, deadline: Instant) -> Result<T, ReceiveTimeoutError> {
+        if let Some(data) = self.pop_buffer() {
+            return Ok(data);
+        }
+
+        let mut inner = lock(&self.shared.inner);
+
+        // Same loop as receive. We check the queue before the clock so a value that showed up right as we timed out still gets picked up,
+        // that also matters for multiple receivers, the one that got notified shouldn't swallow the notification and leave the value sitting there.
+        loop {
+            if !inner.queue.is_empty() {
+                let batch_size = self.shared.batch_size(&inner);
+                self.shared
+                    .move_to_buffer(inner, &mut self.buffer, batch_size);
+                return self.pop_buffer().ok_or(ReceiveTimeoutError::Timeout);
+            }
+
+            if inner.senders == 0 {
+                return Err(ReceiveTimeoutError::Disconnected);
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(ReceiveTimeoutError::Timeout);
+            }
+
+            // wait_timeout can wake up early (spuriously or cause of a notify) so we can't trust the timed out flag it gives back,
+            // the loop compares against the deadline instead.
+            inner = wait_timeout(&self.shared.signal_data_sent, inner, deadline - now);
+        }
+    }
+
+    // Waits like receive until there is at least one value, then hands out up to max of them, all taken under a single lock.
+    // An empty batch means all of the senders are gone and there is nothing left. Same as VecDeque::drain, values you don't iterate over are dropped.
+    // A max of 0 is the exception, there's nothing to wait for so it comes back empty right away, senders or not.
+    pub fn receive_batch(&mut self, max: usize) -> vec_deque::Drain<'_, T> {
+        if max == 0 {
+            return self.buffer.drain(..0);
+        }
+
+        if self.buffer.is_empty() {
+            let mut inner = lock(&self.shared.inner);
+
+            while inner.queue.is_empty() && inner.senders != 0 {
+                inner = wait(&self.shared.signal_data_sent, inner);
+            }
+
+            // A lone receiver on an unbounded channel grabs everything so the next batches are lock free. Otherwise we only take what we hand out,
+            // so the sender's capacity doesn't silently grow by whatever sits in our buffer and other receivers aren't left with nothing.
+            let batch_size = match self.shared.capacity {
+                None if inner.receivers == 1 => usize::MAX,
+                _ => max,
+            };
+            self.shared
+                .move_to_buffer(inner, &mut self.buffer, batch_size);
+        }
+
+        let count = max.min(self.buffer.len());
+        self.shared
+            .total_received
+            .fetch_add(count, Ordering::Relaxed);
+        self.buffer.drain(..count)
+    }
+
+    // Everything that is available right now, never waits. Takes the lock once no matter how many values there are.
+    pub fn drain(&mut self) -> vec_deque::Drain<'_, T> {
+        let inner = lock(&self.shared.inner);
+        self.shared
+            .move_to_buffer(inner, &mut self.buffer, usize::MAX);
+
+        self.shared
+            .total_received
+            .fetch_add(self.buffer.len(), Ordering::Relaxed);
+        self.buffer.drain(..)
+    }
+
+    // Same as receive, but instead of blocking the thread it gives back a future. There's no runtime in here, the executor module has a
+    // block_on and a small single threaded executor to drive it with.
+    pub fn receive_async(&mut self) -> ReceiveFuture<'_, T> {
+        ReceiveFuture { receiver: self }
+    }
+
+    // Every value leaves through here, that's what makes total_received count values handed out instead of values moved into the buffer.
+    fn pop_buffer(&mut self) -> Option<T> {
+        let data = self.buffer.pop_front()?;
+        self.shared.total_received.fetch_add(1, Ordering::Relaxed);
+        Some(data)
+    }
+}
+
+// Same metrics as on the Sender, they all look at the same channel.
+impl<T> Receiver<T> {
+    // Includes whatever sits in our buffer, or any other receiver's, it's been sent and nobody has received it yet.
+    pub fn len(&self) -> usize {
+        self.shared.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> Option<usize> {
+        self.shared.capacity
+    }
+
+    pub fn sender_count(&self) -> usize {
+        lock(&self.shared.inner).senders
+    }
+
+    pub fn receiver_count(&self) -> usize {
+        lock(&self.shared.inner).receivers
+    }
+
+    // For a receiver that means all of the senders are gone. There can still be values left to receive, nothing new is coming though.
+    pub fn is_disconnected(&self) -> bool {
+        self.sender_count() == 0
+    }
+
+    pub fn total_sent(&self) -> usize {
+        self.shared.total_sent.load(Ordering::Relaxed)
+    }
+
+    pub fn total_received(&self) -> usize {
+        self.shared.total_received.load(Ordering::Relaxed)
+    }
+
+    pub fn high_water_mark(&self) -> usize {
+        self.shared.high_water_mark.load(Ordering::Relaxed)
+    }
+
+    // Whether any sender was dropped while its thread was panicking. The channel keeps working either way, what was sent before the panic
+    // is still there to receive, but a receiver that sees None at the end might want to know that it didn't end the way it was supposed to.
+    // Once it's set it stays set.
+    pub fn sender_panicked(&self) -> bool {
+        self.shared.sender_panicked.load(Ordering::Relaxed)
+    }
+}
+
+// Futures do nothing until they're polled. Every poll is basically a try_receive, and when that comes up empty the future leaves its
+// waker with the channel so the next send (or the last sender leaving) wakes it up to be polled again.
+pub struct ReceiveFuture<'a, T> {
+    receiver: &'a mut Receiver<T>,
+}
+
+impl<T> Future for ReceiveFuture<'_, T> {
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Nothing in here is pinned, ReceiveFuture is just a reference, so it's Unpin and we can get at it directly.
+        let receiver = &mut *self.get_mut().receiver;
+
+        if let Some(data) = receiver.pop_buffer() {
+            return Poll::Ready(Some(data));
+        }
+
+        // Checking and registering the waker under the same lock, otherwise a send could slip in between and nobody would wake us up for it.
+        let mut inner = lock(&receiver.shared.inner);
+
+        if !inner.queue.is_empty() {
+            let batch_size = receiver.shared.batch_size(&inner);
+            receiver
+                .shared
+                .move_to_buffer(inner, &mut receiver.buffer, batch_size);
+            return Poll::Ready(receiver.pop_buffer());
+        }
+
+        if inner.senders == 0 {
+            return Poll::Ready(None);
+        }
+
+        // Polled again without having been woken, our waker is most likely still in there.
+        if !inner.wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+            inner.wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+// signal_data_sent belongs to one channel, so a thread waiting on it can only ever hear about that one channel. A Select needs to hear about
+// all of the channels it watches, so it registers one of these with each of them and every one of those channels pokes it.
+// The bool is there for the usual reason, without it a send that happens between checking the channels and waiting would get lost.
+struct Signal {
+    ready: Mutex<bool>,
+    condvar: Condvar,
+}
+
+impl Signal {
+    fn notify(&self) {
+        *lock(&self.ready) = true;
+        self.condvar.notify_all();
+    }
+}
+
+// Waits on several receivers at once and tells you which one is ready. The receivers can carry completely different things, a control
+// channel next to a data channel next to a timer::after, so the Select never touches a value itself. It hands back the index and you call
+// try_receive on that receiver, which is also why add only borrows the receiver for the call, you need it back to receive from.
+//
+// let mut select = Select::new();
+// let control = select.add(&control_receiver);
+// let data = select.add(&data_receiver);
+// match select.wait() {
+//     index if index == control => match control_receiver.try_receive() { ... },
+//     index if index == data => match data_receiver.try_receive() { ... },
+//     _ => unreachable!(),
+// }
+//
+// Ready means try_receive won't come up Empty on its own, there's a value or the channel is disconnected. Another clone of the receiver
+// can still get to the value first though, so an Empty after a wait just means go back to waiting.
+pub struct Select<'a> {
+    // None once removed, so the indices handed out by add stay the same.
+    receivers: Vec<Option<Arc<dyn Selectable + 'a>>>,
+    signal: Arc<Signal>,
+    // Where the next poll starts, so a busy channel at the front can't starve the ones behind it.
+    next_start: usize,
+}
+
+// What a Select needs from a channel, without knowing what the channel carries. The Select holds on to the channel's Shared, not the
+// Receiver, so the receiver is free to be used while it's being watched.
+trait Selectable {
+    fn is_ready(&self) -> bool;
+    fn unregister(&self, signal: &Arc<Signal>);
+}
+
+impl<T> Selectable for Shared<T> {
+    fn is_ready(&self) -> bool {
+        let inner = lock(&self.inner);
+        // len and not the queue, the value can just as well be sitting in the receiver's buffer already. Those are counted as
+        // received only once they're handed out, so a buffered value keeps the channel ready until somebody actually takes it.
+        inner.senders == 0 || self.len() > 0
+    }
+
+    fn unregister(&self, signal: &Arc<Signal>) {
+        let mut inner = lock(&self.inner);
+        inner
+            .selectors
+            .retain(|selector| !Arc::ptr_eq(selector, signal));
+    }
+}
+
+impl Default for Select<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Select<'a> {
+    pub fn new() -> Self {
+        Select {
+            receivers: Vec::new(),
+            signal: Arc::new(Signal {
+                ready: Mutex::new(false),
+                condvar: Condvar::new(),
+            }),
+            next_start: 0,
+        }
+    }
+
+    // Starts watching the receiver and gives back the index wait reports it under.
+    pub fn add<U: 'a>(&mut self, receiver: &Receiver<U>) -> usize {
+        let mut inner = lock(&receiver.shared.inner);
+        inner.selectors.push(Arc::clone(&self.signal));
+        drop(inner);
+
+        let shared: Arc<dyn Selectable + 'a> = Arc::<Shared<U>>::clone(&receiver.shared);
+        self.receivers.push(Some(shared));
+        self.receivers.len() - 1
+    }
+
+    // Stops watching a receiver, typically one that turned out to be disconnected. Otherwise it would come back as ready on every single wait.
+    pub fn remove(&mut self, index: usize) {
+        if let Some(receiver) = self.receivers.get_mut(index).and_then(Option::take) {
+            receiver.unregister(&self.signal);
+        }
+    }
+
+    // Blocks until one of the receivers is ready and gives back its index.
+    // Panics if there is nothing to wait on, that would just block forever.
+    pub fn wait(&mut self) -> usize {
+        self.wait_until(None)
+            .expect("there is no timeout so only a ready receiver ends the wait")
+    }
+
+    // Same as wait but gives up after timeout, None means nothing was ready in time.
+    pub fn wait_timeout(&mut self, timeout: Duration) -> Option<usize> {
+        self.wait_until(receiver::deadline(timeout))
+    }
+
+    pub fn wait_deadline(&mut self, deadline: Instant) -> Option<usize> {
+        self.wait_until(Some(deadline))
+    }
+
+    fn wait_until(&mut self, deadline: Option<Instant>) -> Option<usize> {
+        assert!(
+            self.receivers.iter().any(Option::is_some),
+            "Select has no receivers to wait on"
+        );
+
+        loop {
+            // Clear the flag before we look, anything sent after this point sets it again and the wait below returns right away.
+            *lock(&self.signal.ready) = false;
+
+            if let Some(index) = self.poll() {
+                return Some(index);
+            }
+
+            let mut ready = lock(&self.signal.ready);
+            while !*ready {
+                match deadline {
+                    None => ready = wait(&self.signal.condvar, ready),
+                    Some(deadline) => {
+                        let now = Instant::now();
+                        if now >= deadline {
+                            return None;
+                        }
+                        ready = wait_timeout(&self.signal.condvar, ready, deadline - now);
+                    }
                 }
             }
         }
     }
+
+    // One pass over all of the receivers without blocking.
+    fn poll(&mut self) -> Option<usize> {
+        let count = self.receivers.len();
+        for offset in 0..count {
+            let index = (self.next_start + offset) % count;
+            if let Some(receiver) = &self.receivers[index]
+                && receiver.is_ready()
+            {
+                self.next_start = index + 1;
+                return Some(index);
+            }
+        }
+
+        None
+    }
+}
+
+impl Drop for Select<'_> {
+    fn drop(&mut self) {
+        for receiver in self.receivers.iter().flatten() {
+            receiver.unregister(&self.signal);
+        }
+    }
+}
+
+receiver::iterators!(Receiver);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryReceiveError {
+    // Nothing in the queue right now, but there are still senders around.
+    Empty,
+    // Nothing in the queue and all of the senders are gone.
+    Disconnected,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReceiveTimeoutError {
+    // The deadline passed before anything showed up.
+    Timeout,
+    // Nothing in the queue and all of the senders are gone.
+    Disconnected,
+}
+
+impl std::fmt::Display for TryReceiveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TryReceiveError::Empty => f.write_str("receiving on an empty channel"),
+            TryReceiveError::Disconnected => f.write_str("receiving on a disconnected channel"),
+        }
+    }
+}
+
+impl std::error::Error for TryReceiveError {}
+
+impl std::fmt::Display for ReceiveTimeoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReceiveTimeoutError::Timeout => f.write_str("timed out waiting on the channel"),
+            ReceiveTimeoutError::Disconnected => f.write_str("receiving on a disconnected channel"),
+        }
+    }
 }
 
-// Holds the data structure through which data is exchanged and the number of currently active senders.
+impl std::error::Error for ReceiveTimeoutError {}
+
+// Holds the data structure through which data is exchanged and the number of currently active senders and receivers.
+// received counts every value the receiver has taken out, rendezvous senders use it to find out that their value was picked up.
 struct Inner<T> {
     queue: VecDeque<T>,
     senders: usize,
+    receivers: usize,
+    received: usize,
+    // Every Select currently watching this channel. They get poked on every send and when the last sender goes away.
+    selectors: Vec<Arc<Signal>>,
+    // Same idea for futures from receive_async that came up empty. Unlike selectors they're one shot, a future that still wants
+    // to wait after being woken puts its waker back in when it's polled again.
+    wakers: Vec<Waker>,
+}
+
+impl<T> Inner<T> {
+    // Called with the channel's lock held. That's fine lock order wise, a Select never takes a channel's lock while holding its own signal's lock.
+    // The wakers are handed back instead of woken right here, waking runs whatever code the executor wants and that shouldn't happen
+    // while we hold the lock. The caller wakes them once it has let go.
+    #[must_use]
+    fn notify_selectors(&mut self) -> Vec<Waker> {
+        for selector in &self.selectors {
+            selector.notify();
+        }
+        std::mem::take(&mut self.wakers)
+    }
 }
 
 // Holds inner struct for state, and a conditional variable to signal that one of the senders has sent data that the receiver can consume.
+// The second conditional variable goes the other way, the receiver uses it to signal that it took something out and a bounded queue has space again.
 struct Shared<T> {
     inner: Mutex<Inner<T>>,
     signal_data_sent: Condvar,
+    signal_space_available: Condvar,
+    // None means unbounded. It never changes after construction so it lives outside the Mutex.
+    capacity: Option<usize>,
+    // Counters for the metrics. Atomics so that looking at them never has to wait for the lock, they're only ever read to show somebody a number,
+    // nothing is synchronized through them, hence Relaxed everywhere.
+    // Every value a send put in and every value a receiver handed out. Handed out, not taken off the queue, the values sitting in a receiver's
+    // buffer haven't been received by anybody yet.
+    total_sent: AtomicUsize,
+    total_received: AtomicUsize,
+    // The most values that were ever waiting to be received at once.
+    high_water_mark: AtomicUsize,
+    // Set by a sender that's dropped during a panic. Nothing is synchronized through it either, the lock in Sender::drop takes care of that.
+    sender_panicked: AtomicBool,
 }
 
-// Its convention to return the Sender first and then the Receiver.
-pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
-    let shared = Shared {
-        inner: Mutex::new(Inner {
-            queue: VecDeque::new(),
-            senders: 1,
-        }),
-        signal_data_sent: Condvar::new(),
-    };
-    let shared = Arc::new(shared);
+impl<T> Shared<T> {
+    // How many values a single receive pulls out of the shared queue. A lone receiver on an unbounded channel takes all of it, there's nobody
+    // waiting on space and nobody else who could have used them. With cloned receivers it's just the one, anything more would sit in our
+    // buffer while the others go without, a worker pool where one worker has all the work. Bounded and rendezvous channels always take
+    // just the one, otherwise the receiver's buffer would quietly act as extra capacity and a rendezvous sender would be let go before
+    // anybody actually got its value.
+    fn batch_size(&self, inner: &Inner<T>) -> usize {
+        match self.capacity {
+            None if inner.receivers == 1 => usize::MAX,
+            _ => 1,
+        }
+    }
+
+    // Puts a value at the back of the queue and lets everybody waiting on it know. Takes the guard for the same reason as move_to_buffer.
+    fn push(&self, mut inner: MutexGuard<'_, Inner<T>>, data: T) {
+        inner.queue.push_back(data);
+        self.record_sent();
+        let wakers = inner.notify_selectors();
+        // We need to drop the queue and its held lock otherwise the other thread would wake up but never get the Mutex, likely a deadlock.
+        drop(inner);
+        wakers.into_iter().for_each(Waker::wake);
+
+        self.signal_data_sent.notify_one();
+    }
+
+    // Called under the lock right after a value went in. The receivers don't take the lock to hand out what's in their buffers though,
+    // so the received count can move on under our feet, hence the saturating_sub. The mark is a close guess, not an exact number.
+    fn record_sent(&self) {
+        let sent = self.total_sent.fetch_add(1, Ordering::Relaxed) + 1;
+        let len = sent.saturating_sub(self.total_received.load(Ordering::Relaxed));
+        self.high_water_mark.fetch_max(len, Ordering::Relaxed);
+    }
+
+    // Sent but not received yet, whether that's in the queue or in some receiver's buffer.
+    fn len(&self) -> usize {
+        // received first. The other way round, a send and its receive could both happen in between and received would come out ahead of sent.
+        let received = self.total_received.load(Ordering::Relaxed);
+        self.total_sent
+            .load(Ordering::Relaxed)
+            .saturating_sub(received)
+    }
+
+    // Moves up to max values from the shared queue into a receiver's buffer. Every successful receive ends with this.
+    // It takes the guard so the lock is released before we notify anyone.
+    fn move_to_buffer(
+        &self,
+        mut inner: MutexGuard<'_, Inner<T>>,
+        buffer: &mut VecDeque<T>,
+        max: usize,
+    ) {
+        let count = max.min(inner.queue.len());
+        if buffer.is_empty() && count == inner.queue.len() {
+            // Taking everything into an empty buffer is just swapping the two VecDeques, no matter how many values there are.
+            // The queue gets the buffer's old allocation so neither side has to grow from scratch again.
+            std::mem::swap(buffer, &mut inner.queue);
+        } else {
+            buffer.extend(inner.queue.drain(..count));
+        }
+        inner.received += count;
+        // We made room in the queue, so wake up a sender that might be blocked on a full bounded channel.
+        // Dropping before notifying for the same reason as in send.
+        drop(inner);
+
+        // In the rendezvous case the senders waiting on this condvar wait for different things, one for its value to be taken and others
+        // for the slot to free up. notify_one could wake the wrong one and leave the right one sleeping, so everybody gets woken up.
+        // Same if we made room for more than one sender.
+        if self.capacity == Some(0) || count > 1 {
+            self.signal_space_available.notify_all();
+        } else if count == 1 {
+            self.signal_space_available.notify_one();
+        }
+    }
+}
+
+// Its convention to return the Sender first and then the Receiver.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    new_channel(None)
+}
+
+// Same thing as channel but the queue holds at most capacity items. Once it's full Sender::send blocks until the receiver drains something.
+// A capacity of 0 gives you a rendezvous channel, send only returns once a receive has taken the value, so both threads have to meet.
+pub fn sync_channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    new_channel(Some(capacity))
+}
+
+fn new_channel<T>(capacity: Option<usize>) -> (Sender<T>, Receiver<T>) {
+    let shared = Shared {
+        inner: Mutex::new(Inner {
+            queue: VecDeque::new(),
+            senders: 1,
+            receivers: 1,
+            received: 0,
+            selectors: Vec::new(),
+            wakers: Vec::new(),
+        }),
+        signal_data_sent: Condvar::new(),
+        signal_space_available: Condvar::new(),
+        capacity,
+        total_sent: AtomicUsize::new(0),
+        total_received: AtomicUsize::new(0),
+        high_water_mark: AtomicUsize::new(0),
+        sender_panicked: AtomicBool::new(false),
+    };
+    let shared = Arc::new(shared);
 
     (
         Sender {
@@ -120,6 +952,7 @@ pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
         },
         Receiver {
             shared: shared.clone(),
+            buffer: VecDeque::new(),
         },
     )
 }
@@ -127,11 +960,12 @@ pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_util::DropCounter;
 
     #[test]
     fn ping_pong() {
         let (mut sender, mut receiver) = channel();
-        sender.send(42);
+        sender.send(42).unwrap();
         assert_eq!(receiver.receive(), Some(42));
     }
 
@@ -144,4 +978,1155 @@ mod tests {
 
         assert_eq!(receiver.receive(), None);
     }
+
+    #[test]
+    fn sync_channel_ping_pong() {
+        let (mut sender, mut receiver) = sync_channel(1);
+        sender.send(42).unwrap();
+        assert_eq!(receiver.receive(), Some(42));
+        sender.send(43).unwrap();
+        assert_eq!(receiver.receive(), Some(43));
+    }
+
+    #[test]
+    fn sync_channel_fills_up_without_blocking() {
+        let (mut sender, mut receiver) = sync_channel(3);
+        for i in 0..3 {
+            sender.send(i).unwrap();
+        }
+
+        for i in 0..3 {
+            assert_eq!(receiver.receive(), Some(i));
+        }
+    }
+
+    #[test]
+    fn sync_channel_blocks_when_full() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::time::Duration;
+
+        let (mut sender, mut receiver) = sync_channel(2);
+        let sent_third = Arc::new(AtomicBool::new(false));
+
+        let t = std::thread::spawn({
+            let sent_third = sent_third.clone();
+            move || {
+                sender.send(1).unwrap();
+                sender.send(2).unwrap();
+                // The queue is full at this point so this one has to wait for the receiver.
+                sender.send(3).unwrap();
+                sent_third.store(true, Ordering::Release);
+            }
+        });
+
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(!sent_third.load(Ordering::Acquire));
+
+        assert_eq!(receiver.receive(), Some(1));
+        t.join().unwrap();
+        assert!(sent_third.load(Ordering::Acquire));
+
+        assert_eq!(receiver.receive(), Some(2));
+        assert_eq!(receiver.receive(), Some(3));
+    }
+
+    #[test]
+    fn sync_channel_receive_waits_on_empty() {
+        let (mut sender, mut receiver) = sync_channel(1);
+
+        let t = std::thread::spawn(move || receiver.receive());
+
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        sender.send(7).unwrap();
+        assert_eq!(t.join().unwrap(), Some(7));
+    }
+
+    #[test]
+    fn sync_channel_keeps_order_under_backpressure() {
+        let (mut sender, mut receiver) = sync_channel(1);
+
+        let t = std::thread::spawn(move || {
+            for i in 0..1000 {
+                sender.send(i).unwrap();
+            }
+        });
+
+        for i in 0..1000 {
+            assert_eq!(receiver.receive(), Some(i));
+        }
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn sync_channel_drop_sender_on_empty() {
+        let (sender, mut receiver) = sync_channel::<()>(4);
+        drop(sender);
+
+        assert_eq!(receiver.receive(), None);
+    }
+
+    #[test]
+    fn rendezvous_ping_pong() {
+        let (mut sender, mut receiver) = sync_channel(0);
+
+        let t = std::thread::spawn(move || {
+            sender.send(42).unwrap();
+            sender.send(43).unwrap();
+        });
+
+        assert_eq!(receiver.receive(), Some(42));
+        assert_eq!(receiver.receive(), Some(43));
+        t.join().unwrap();
+        // The sender went away with the thread.
+        assert_eq!(receiver.receive(), None);
+    }
+
+    #[test]
+    fn rendezvous_send_waits_for_receive() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::time::Duration;
+
+        let (mut sender, mut receiver) = sync_channel(0);
+        let sent = Arc::new(AtomicBool::new(false));
+
+        let t = std::thread::spawn({
+            let sent = sent.clone();
+            move || {
+                sender.send(1).unwrap();
+                sent.store(true, Ordering::Release);
+            }
+        });
+
+        // Unlike a bounded channel there is no room for even a single value, so the send can't be done until we receive it.
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(!sent.load(Ordering::Acquire));
+
+        assert_eq!(receiver.receive(), Some(1));
+        t.join().unwrap();
+        assert!(sent.load(Ordering::Acquire));
+    }
+
+    #[test]
+    fn rendezvous_multiple_senders() {
+        let (sender, mut receiver) = sync_channel(0);
+
+        let handles: Vec<_> = (0..4)
+            .map(|thread| {
+                let mut sender = sender.clone();
+                std::thread::spawn(move || {
+                    for i in 0..100 {
+                        sender.send(thread * 100 + i).unwrap();
+                    }
+                })
+            })
+            .collect();
+
+        let mut received: Vec<_> = (0..400).map(|_| receiver.receive().unwrap()).collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        received.sort();
+        assert_eq!(received, (0..400).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn cloned_receivers_split_the_messages() {
+        let (mut sender, receiver) = channel();
+
+        // The workers stop at None, one None per worker, that way none of them sits around waiting for a message that is never coming.
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                let mut receiver = receiver.clone();
+                std::thread::spawn(move || {
+                    let mut got = Vec::new();
+                    while let Some(Some(data)) = receiver.receive() {
+                        got.push(data);
+                    }
+                    got
+                })
+            })
+            .collect();
+
+        for i in 0..1000 {
+            sender.send(Some(i)).unwrap();
+        }
+        for _ in 0..4 {
+            sender.send(None).unwrap();
+        }
+
+        let mut all: Vec<_> = workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect();
+        all.sort();
+        // Each message went to exactly one worker, nothing got lost and nothing got duplicated.
+        assert_eq!(all, (0..1000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn cloned_receivers_dont_hoard() {
+        let (mut sender, mut busy) = channel();
+        let mut idle = busy.clone();
+        for i in 0..10 {
+            sender.send(i).unwrap();
+        }
+
+        // busy takes a single value and goes off to work on it, the rest has to stay up for grabs.
+        assert_eq!(busy.receive(), Some(0));
+        assert!(busy.buffer.is_empty());
+        assert_eq!(idle.try_receive(), Ok(1));
+        assert_eq!(idle.receive_batch(3).collect::<Vec<_>>(), vec![2, 3, 4]);
+        assert_eq!(idle.buffer.len(), 0);
+
+        // Back down to one receiver, it takes the whole queue again.
+        drop(busy);
+        assert_eq!(idle.receive(), Some(5));
+        assert_eq!(idle.buffer.len(), 4);
+    }
+
+    #[test]
+    fn send_fails_without_receivers() {
+        let (mut sender, receiver) = channel();
+        let second_receiver = receiver.clone();
+
+        drop(receiver);
+        // One receiver is still around.
+        assert_eq!(sender.send(1), Ok(()));
+
+        drop(second_receiver);
+        assert_eq!(sender.send(2), Err(SendError(2)));
+    }
+
+    #[test]
+    fn full_sync_channel_sender_wakes_up_when_receivers_drop() {
+        let (mut sender, receiver) = sync_channel(1);
+        sender.send(1).unwrap();
+
+        let t = std::thread::spawn(move || sender.send(2));
+
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        drop(receiver);
+        assert_eq!(t.join().unwrap(), Err(SendError(2)));
+    }
+
+    #[test]
+    fn rendezvous_sender_gets_value_back_when_receivers_drop() {
+        let (mut sender, receiver) = sync_channel(0);
+
+        let t = std::thread::spawn(move || sender.send(String::from("hello")));
+
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        drop(receiver);
+        assert_eq!(t.join().unwrap(), Err(SendError(String::from("hello"))));
+    }
+
+    #[test]
+    fn try_receive_empty_then_value() {
+        let (mut sender, mut receiver) = channel();
+        assert_eq!(receiver.try_receive(), Err(TryReceiveError::Empty));
+
+        sender.send(1).unwrap();
+        assert_eq!(receiver.try_receive(), Ok(1));
+        assert_eq!(receiver.try_receive(), Err(TryReceiveError::Empty));
+    }
+
+    #[test]
+    fn try_receive_disconnected_after_queue_drains() {
+        let (mut sender, mut receiver) = channel();
+        sender.send(1).unwrap();
+        drop(sender);
+
+        // What was sent before the senders left is still delivered.
+        assert_eq!(receiver.try_receive(), Ok(1));
+        assert_eq!(receiver.try_receive(), Err(TryReceiveError::Disconnected));
+    }
+
+    #[test]
+    fn try_receive_frees_space_in_sync_channel() {
+        let (mut sender, mut receiver) = sync_channel(1);
+        sender.send(1).unwrap();
+
+        let t = std::thread::spawn(move || sender.send(2));
+
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert_eq!(receiver.try_receive(), Ok(1));
+        t.join().unwrap().unwrap();
+        assert_eq!(receiver.try_receive(), Ok(2));
+    }
+
+    #[test]
+    fn receive_timeout_times_out() {
+        // Keeping the sender alive, otherwise we'd get Disconnected right away.
+        let (_sender, mut receiver) = channel::<()>();
+
+        let start = Instant::now();
+        assert_eq!(
+            receiver.receive_timeout(Duration::from_millis(50)),
+            Err(ReceiveTimeoutError::Timeout)
+        );
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn receive_timeout_gets_value_sent_while_waiting() {
+        let (mut sender, mut receiver) = channel();
+
+        let t = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            sender.send(5).unwrap();
+            sender
+        });
+
+        assert_eq!(receiver.receive_timeout(Duration::from_secs(10)), Ok(5));
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn receive_timeout_disconnected() {
+        let (sender, mut receiver) = channel::<()>();
+        drop(sender);
+
+        assert_eq!(
+            receiver.receive_timeout(Duration::from_secs(10)),
+            Err(ReceiveTimeoutError::Disconnected)
+        );
+    }
+
+    #[test]
+    fn receive_deadline_in_the_past() {
+        let (mut sender, mut receiver) = channel();
+        let deadline = Instant::now();
+
+        assert_eq!(
+            receiver.receive_deadline(deadline),
+            Err(ReceiveTimeoutError::Timeout)
+        );
+
+        // A value that is already there is handed out even if the deadline has passed.
+        sender.send(3).unwrap();
+        assert_eq!(receiver.receive_deadline(deadline), Ok(3));
+    }
+
+    #[test]
+    fn receive_timeout_does_not_overflow() {
+        let (mut sender, mut receiver) = channel();
+        sender.send(1).unwrap();
+
+        assert_eq!(receiver.receive_timeout(Duration::MAX), Ok(1));
+    }
+
+    // Drop order tests. Every combination of who goes away first, and whether the other side is sleeping at the time.
+
+    #[test]
+    fn drop_sender_while_receiver_waits() {
+        let (sender, mut receiver) = channel::<()>();
+
+        let t = std::thread::spawn(move || receiver.receive());
+
+        std::thread::sleep(Duration::from_millis(50));
+        drop(sender);
+        assert_eq!(t.join().unwrap(), None);
+    }
+
+    #[test]
+    fn drop_one_of_two_senders_while_receiver_waits() {
+        let (mut sender, mut receiver) = channel();
+        let second_sender = sender.clone();
+
+        let t = std::thread::spawn(move || receiver.receive());
+
+        std::thread::sleep(Duration::from_millis(50));
+        // There's still one sender so the receiver has to keep waiting.
+        drop(second_sender);
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(!t.is_finished());
+
+        sender.send(1).unwrap();
+        assert_eq!(t.join().unwrap(), Some(1));
+    }
+
+    #[test]
+    fn drop_original_sender_keep_clone() {
+        let (sender, mut receiver) = channel();
+        let mut cloned = sender.clone();
+        drop(sender);
+
+        cloned.send(1).unwrap();
+        assert_eq!(receiver.receive(), Some(1));
+
+        drop(cloned);
+        assert_eq!(receiver.receive(), None);
+    }
+
+    #[test]
+    fn drop_all_senders_wakes_every_receiver() {
+        let (sender, receiver) = channel::<()>();
+
+        let waiting: Vec<_> = (0..4)
+            .map(|_| {
+                let mut receiver = receiver.clone();
+                std::thread::spawn(move || receiver.receive())
+            })
+            .collect();
+
+        std::thread::sleep(Duration::from_millis(50));
+        drop(sender);
+        for t in waiting {
+            assert_eq!(t.join().unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn drop_sender_after_sending() {
+        let (mut sender, mut receiver) = channel();
+        sender.send(1).unwrap();
+        sender.send(2).unwrap();
+        drop(sender);
+
+        // Whatever was sent before the senders left is still delivered, only then do we get None.
+        assert_eq!(receiver.receive(), Some(1));
+        assert_eq!(receiver.receive(), Some(2));
+        assert_eq!(receiver.receive(), None);
+    }
+
+    #[test]
+    fn drop_receiver_first() {
+        let (mut sender, receiver) = channel();
+        drop(receiver);
+
+        let error = sender.send(String::from("lost")).unwrap_err();
+        assert_eq!(error.into_inner(), "lost");
+        // Dropping the sender afterwards is fine as well.
+        drop(sender);
+    }
+
+    #[test]
+    fn drop_receiver_drops_queued_values() {
+        let drops = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let (mut sender, receiver) = channel();
+        sender.send(DropCounter(drops.clone())).unwrap();
+        sender.send(DropCounter(drops.clone())).unwrap();
+
+        // The sender is still alive, but nobody can read these anymore so they shouldn't stick around.
+        drop(receiver);
+        assert_eq!(drops.load(std::sync::atomic::Ordering::Relaxed), 2);
+
+        // And the one we try to send afterwards comes back to us instead of piling up.
+        let error = sender.send(DropCounter(drops.clone())).unwrap_err();
+        drop(error);
+        assert_eq!(drops.load(std::sync::atomic::Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn drop_sender_and_receiver_with_values_queued() {
+        let drops = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let (mut sender, receiver) = channel();
+        sender.send(DropCounter(drops.clone())).unwrap();
+
+        drop(sender);
+        assert_eq!(drops.load(std::sync::atomic::Ordering::Relaxed), 0);
+        drop(receiver);
+        assert_eq!(drops.load(std::sync::atomic::Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn drop_one_of_two_receivers() {
+        let (mut sender, receiver) = channel();
+        let mut second_receiver = receiver.clone();
+        drop(receiver);
+
+        sender.send(1).unwrap();
+        assert_eq!(second_receiver.receive(), Some(1));
+    }
+
+    #[test]
+    fn drop_receiver_while_sender_waits_on_rendezvous() {
+        let (mut sender, receiver) = sync_channel(0);
+
+        let t = std::thread::spawn(move || sender.send(1));
+
+        std::thread::sleep(Duration::from_millis(50));
+        drop(receiver);
+        assert_eq!(t.join().unwrap(), Err(SendError(1)));
+    }
+
+    #[test]
+    fn drop_sender_while_receiver_waits_on_sync_channel() {
+        let (sender, mut receiver) = sync_channel::<()>(1);
+
+        let t = std::thread::spawn(move || receiver.receive_timeout(Duration::from_secs(10)));
+
+        std::thread::sleep(Duration::from_millis(50));
+        drop(sender);
+        assert_eq!(t.join().unwrap(), Err(ReceiveTimeoutError::Disconnected));
+    }
+
+    #[test]
+    fn send_error_does_not_need_debug() {
+        struct NotDebug;
+
+        let (mut sender, receiver) = channel();
+        drop(receiver);
+        let error = sender.send(NotDebug).unwrap_err();
+        assert_eq!(format!("{error:?}"), "SendError { .. }");
+        assert_eq!(error.to_string(), "sending on a disconnected channel");
+    }
+
+    #[test]
+    fn for_loop_over_receiver() {
+        let (mut sender, receiver) = channel();
+
+        let t = std::thread::spawn(move || {
+            for i in 0..10 {
+                sender.send(i).unwrap();
+            }
+        });
+
+        let mut expected = 0;
+        // Ends when the thread is done and its sender is dropped.
+        for data in receiver {
+            assert_eq!(data, expected);
+            expected += 1;
+        }
+        assert_eq!(expected, 10);
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn iter_with_std_combinators() {
+        let (mut sender, mut receiver) = channel();
+        for i in 1..=4 {
+            sender.send(i).unwrap();
+        }
+        drop(sender);
+
+        let sum: i32 = receiver.iter().map(|data| data * 10).sum();
+        assert_eq!(sum, 100);
+        // The receiver is still usable after the borrowing iterator is done with it.
+        assert_eq!(receiver.receive(), None);
+    }
+
+    #[test]
+    fn iter_with_our_flatten() {
+        let (mut sender, receiver) = channel();
+        sender.send(vec![1, 2]).unwrap();
+        sender.send(vec![]).unwrap();
+        sender.send(vec![3]).unwrap();
+        drop(sender);
+
+        let all: Vec<_> = crate::iterators::flatten(receiver).collect();
+        assert_eq!(all, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn try_iter_stops_when_empty() {
+        let (mut sender, mut receiver) = channel();
+        sender.send(1).unwrap();
+        sender.send(2).unwrap();
+
+        // The sender is still alive, try_iter doesn't wait for it.
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![1, 2]);
+
+        sender.send(3).unwrap();
+        assert_eq!(receiver.try_iter().next(), Some(3));
+        assert_eq!(receiver.try_iter().next(), None);
+    }
+
+    #[test]
+    fn for_loop_over_borrowed_receiver() {
+        let (mut sender, mut receiver) = channel();
+        sender.send(1).unwrap();
+        drop(sender);
+
+        let mut got = Vec::new();
+        for data in &mut receiver {
+            got.push(data);
+        }
+        assert_eq!(got, vec![1]);
+    }
+
+    #[test]
+    fn receive_swaps_out_the_whole_queue() {
+        let (mut sender, mut receiver) = channel();
+        for i in 0..5 {
+            sender.send(i).unwrap();
+        }
+
+        assert_eq!(receiver.receive(), Some(0));
+        // One lock, and everything else came along with it.
+        assert!(receiver.shared.inner.lock().unwrap().queue.is_empty());
+        assert_eq!(receiver.buffer.len(), 4);
+
+        sender.send(5).unwrap();
+        assert_eq!(
+            receiver.iter().take(5).collect::<Vec<_>>(),
+            vec![1, 2, 3, 4, 5]
+        );
+    }
+
+    #[test]
+    fn receive_on_sync_channel_takes_one_at_a_time() {
+        let (mut sender, mut receiver) = sync_channel(3);
+        for i in 0..3 {
+            sender.send(i).unwrap();
+        }
+
+        assert_eq!(receiver.receive(), Some(0));
+        assert_eq!(receiver.shared.inner.lock().unwrap().queue.len(), 2);
+        assert!(receiver.buffer.is_empty());
+    }
+
+    #[test]
+    fn receive_batch_respects_max() {
+        let (mut sender, mut receiver) = channel();
+        for i in 0..10 {
+            sender.send(i).unwrap();
+        }
+
+        assert_eq!(
+            receiver.receive_batch(4).collect::<Vec<_>>(),
+            vec![0, 1, 2, 3]
+        );
+        assert_eq!(
+            receiver.receive_batch(4).collect::<Vec<_>>(),
+            vec![4, 5, 6, 7]
+        );
+        assert_eq!(receiver.receive_batch(4).collect::<Vec<_>>(), vec![8, 9]);
+
+        drop(sender);
+        assert_eq!(receiver.receive_batch(4).count(), 0);
+    }
+
+    #[test]
+    fn receive_batch_of_zero_never_waits() {
+        let (mut sender, mut receiver) = channel::<i32>();
+        assert_eq!(receiver.receive_batch(0).count(), 0);
+
+        // Doesn't touch anything that's waiting either.
+        sender.send(1).unwrap();
+        assert_eq!(receiver.receive_batch(0).count(), 0);
+        assert_eq!(receiver.receive(), Some(1));
+    }
+
+    #[test]
+    fn receive_batch_waits_for_the_first_value() {
+        let (mut sender, mut receiver) = channel();
+
+        let t = std::thread::spawn(move || receiver.receive_batch(10).collect::<Vec<_>>());
+
+        std::thread::sleep(Duration::from_millis(50));
+        sender.send(1).unwrap();
+        assert_eq!(t.join().unwrap(), vec![1]);
+    }
+
+    #[test]
+    fn receive_batch_on_sync_channel_frees_every_slot() {
+        let (mut sender, mut receiver) = sync_channel(2);
+        sender.send(1).unwrap();
+        sender.send(2).unwrap();
+
+        // Two senders waiting on space, taking both values out must wake both of them.
+        let mut second_sender = sender.clone();
+        let first = std::thread::spawn(move || sender.send(3));
+        let second = std::thread::spawn(move || second_sender.send(4));
+
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(receiver.receive_batch(2).collect::<Vec<_>>(), vec![1, 2]);
+        first.join().unwrap().unwrap();
+        second.join().unwrap().unwrap();
+
+        let mut rest: Vec<_> = receiver.drain().collect();
+        rest.sort();
+        assert_eq!(rest, vec![3, 4]);
+    }
+
+    #[test]
+    fn drain_never_waits() {
+        let (mut sender, mut receiver) = channel();
+        assert_eq!(receiver.drain().count(), 0);
+
+        sender.send(1).unwrap();
+        sender.send(2).unwrap();
+        assert_eq!(receiver.receive(), Some(1));
+        sender.send(3).unwrap();
+
+        // What's in the buffer comes first, then the shared queue.
+        assert_eq!(receiver.drain().collect::<Vec<_>>(), vec![2, 3]);
+    }
+
+    #[test]
+    fn dropped_receiver_hands_buffer_back() {
+        let (mut sender, mut receiver) = channel();
+        let mut other_receiver = receiver.clone();
+        for i in 0..4 {
+            sender.send(i).unwrap();
+        }
+
+        assert_eq!(receiver.receive(), Some(0));
+        drop(receiver);
+
+        assert_eq!(other_receiver.drain().collect::<Vec<_>>(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn select_picks_the_ready_receiver() {
+        let (mut control_sender, mut control) = channel();
+        let (mut data_sender, mut data) = channel();
+
+        let mut select = Select::new();
+        let control_index = select.add(&control);
+        let data_index = select.add(&data);
+
+        data_sender.send("data").unwrap();
+        assert_eq!(select.wait(), data_index);
+        assert_eq!(data.try_receive(), Ok("data"));
+
+        control_sender.send("stop").unwrap();
+        assert_eq!(select.wait(), control_index);
+        assert_eq!(control.try_receive(), Ok("stop"));
+    }
+
+    #[test]
+    fn select_over_different_types() {
+        #[derive(Debug, PartialEq)]
+        enum Control {
+            Stop,
+        }
+
+        let (mut control_sender, mut control) = channel();
+        let (mut data_sender, mut data) = channel::<Vec<u8>>();
+
+        let mut select = Select::new();
+        let control_index = select.add(&control);
+        let data_index = select.add(&data);
+
+        data_sender.send(vec![1, 2, 3]).unwrap();
+        control_sender.send(Control::Stop).unwrap();
+
+        let mut got_data = None;
+        let mut got_control = None;
+        for _ in 0..2 {
+            match select.wait() {
+                index if index == data_index => got_data = data.try_receive().ok(),
+                index if index == control_index => got_control = control.try_receive().ok(),
+                index => panic!("unknown index {index}"),
+            }
+        }
+        assert_eq!(got_data, Some(vec![1, 2, 3]));
+        assert_eq!(got_control, Some(Control::Stop));
+    }
+
+    #[test]
+    fn select_waits_for_another_thread() {
+        let (mut first_sender, mut first) = channel();
+        let (second_sender, second) = channel::<()>();
+
+        let t = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            first_sender.send(1).unwrap();
+            first_sender
+        });
+
+        let mut select = Select::new();
+        select.add(&first);
+        select.add(&second);
+        assert_eq!(select.wait(), 0);
+        assert_eq!(first.try_receive(), Ok(1));
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn select_is_fair_between_busy_receivers() {
+        let (mut first_sender, mut first) = channel();
+        let (mut second_sender, mut second) = channel();
+        for i in 0..3 {
+            first_sender.send(i).unwrap();
+            second_sender.send(i).unwrap();
+        }
+
+        let mut select = Select::new();
+        select.add(&first);
+        select.add(&second);
+        let indices: Vec<_> = (0..4)
+            .map(|_| {
+                let index = select.wait();
+                match index {
+                    0 => first.try_receive().unwrap(),
+                    _ => second.try_receive().unwrap(),
+                };
+                index
+            })
+            .collect();
+        assert_eq!(indices, vec![0, 1, 0, 1]);
+    }
+
+    #[test]
+    fn select_sees_values_in_the_receivers_buffer() {
+        let (mut sender, mut receiver) = channel();
+        for i in 0..3 {
+            sender.send(i).unwrap();
+        }
+        // A lone unbounded receiver pulls all three into its buffer, the shared queue is empty now.
+        assert_eq!(receiver.receive(), Some(0));
+
+        let mut select = Select::new();
+        select.add(&receiver);
+        assert_eq!(select.wait_timeout(Duration::from_millis(20)), Some(0));
+        assert_eq!(receiver.try_receive(), Ok(1));
+        assert_eq!(select.wait_timeout(Duration::from_millis(20)), Some(0));
+        assert_eq!(receiver.try_receive(), Ok(2));
+        assert_eq!(select.wait_timeout(Duration::from_millis(20)), None);
+    }
+
+    #[test]
+    fn select_timeout() {
+        let (_sender, receiver) = channel::<()>();
+
+        let mut select = Select::new();
+        select.add(&receiver);
+        assert_eq!(select.wait_timeout(Duration::from_millis(50)), None);
+    }
+
+    #[test]
+    fn select_reports_disconnected_receiver() {
+        let (sender, mut closing) = channel::<i32>();
+        let (mut other_sender, mut other) = channel();
+
+        let mut select = Select::new();
+        let closing_index = select.add(&closing);
+        let other_index = select.add(&other);
+
+        drop(sender);
+        assert_eq!(select.wait(), closing_index);
+        assert_eq!(closing.try_receive(), Err(TryReceiveError::Disconnected));
+
+        // Once it's out of the way we get to wait on the rest.
+        select.remove(closing_index);
+        assert_eq!(select.wait_timeout(Duration::from_millis(20)), None);
+        other_sender.send(5).unwrap();
+        assert_eq!(select.wait(), other_index);
+        assert_eq!(other.try_receive(), Ok(5));
+    }
+
+    #[test]
+    fn select_wakes_up_on_disconnect() {
+        let (sender, receiver) = channel::<()>();
+
+        let t = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            drop(sender);
+        });
+
+        let mut select = Select::new();
+        select.add(&receiver);
+        assert_eq!(select.wait(), 0);
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn select_unregisters_on_drop() {
+        let (mut sender, mut receiver) = channel();
+
+        let mut select = Select::new();
+        select.add(&receiver);
+        assert_eq!(receiver_selectors(&sender), 1);
+        drop(select);
+        assert_eq!(receiver_selectors(&sender), 0);
+
+        // And the receiver is usable on its own again.
+        sender.send(1).unwrap();
+        assert_eq!(receiver.receive(), Some(1));
+    }
+
+    fn receiver_selectors<T>(sender: &Sender<T>) -> usize {
+        sender.shared.inner.lock().unwrap().selectors.len()
+    }
+
+    #[test]
+    fn receive_async_waits_for_send() {
+        let (mut sender, mut receiver) = channel();
+
+        let t = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            sender.send(1).unwrap();
+            sender.send(2).unwrap();
+        });
+
+        assert_eq!(crate::executor::block_on(receiver.receive_async()), Some(1));
+        t.join().unwrap();
+        // The second one could be in our buffer by now, either way it's the next one out.
+        assert_eq!(crate::executor::block_on(receiver.receive_async()), Some(2));
+    }
+
+    #[test]
+    fn receive_async_none_once_senders_are_gone() {
+        let (sender, mut receiver) = channel::<()>();
+
+        let t = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            drop(sender);
+        });
+
+        assert_eq!(crate::executor::block_on(receiver.receive_async()), None);
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn receive_async_on_rendezvous() {
+        let (mut sender, mut receiver) = sync_channel(0);
+
+        let t = std::thread::spawn(move || sender.send(1));
+
+        assert_eq!(crate::executor::block_on(receiver.receive_async()), Some(1));
+        assert_eq!(t.join().unwrap(), Ok(()));
+    }
+
+    #[test]
+    fn pending_receive_async_registers_its_waker_once() {
+        let (mut sender, mut receiver) = channel();
+        let wakes = Arc::new(WakeCounter(std::sync::atomic::AtomicUsize::new(0)));
+        let waker = Waker::from(Arc::clone(&wakes));
+        let mut cx = Context::from_waker(&waker);
+
+        let mut future = receiver.receive_async();
+        assert_eq!(Pin::new(&mut future).poll(&mut cx), Poll::Pending);
+        assert_eq!(Pin::new(&mut future).poll(&mut cx), Poll::Pending);
+        assert_eq!(receiver_wakers(&sender), 1);
+
+        // Sending wakes it and takes it out, it's up to the next poll to put it back in.
+        sender.send(1).unwrap();
+        assert_eq!(wakes.0.load(std::sync::atomic::Ordering::Relaxed), 1);
+        assert_eq!(receiver_wakers(&sender), 0);
+        assert_eq!(Pin::new(&mut future).poll(&mut cx), Poll::Ready(Some(1)));
+    }
+
+    // Counts how many times it was woken.
+    struct WakeCounter(std::sync::atomic::AtomicUsize);
+
+    impl std::task::Wake for WakeCounter {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn try_send_on_full_channel() {
+        let (mut sender, mut receiver) = sync_channel(1);
+        assert_eq!(sender.try_send(1), Ok(()));
+        assert_eq!(sender.try_send(2), Err(TrySendError::Full(2)));
+
+        assert_eq!(receiver.receive(), Some(1));
+        assert_eq!(sender.try_send(2), Ok(()));
+        assert_eq!(receiver.receive(), Some(2));
+    }
+
+    #[test]
+    fn try_send_never_fills_unbounded() {
+        let (mut sender, mut receiver) = channel();
+        for i in 0..100 {
+            sender.try_send(i).unwrap();
+        }
+        assert_eq!(receiver.try_iter().count(), 100);
+    }
+
+    #[test]
+    fn try_send_on_rendezvous_is_always_full() {
+        let (mut sender, _receiver) = sync_channel(0);
+        assert_eq!(sender.try_send(1), Err(TrySendError::Full(1)));
+    }
+
+    #[test]
+    fn try_send_without_receivers() {
+        let (mut sender, receiver) = sync_channel(1);
+        drop(receiver);
+        assert_eq!(
+            sender
+                .try_send(String::from("lost"))
+                .unwrap_err()
+                .into_inner(),
+            "lost"
+        );
+        assert!(matches!(
+            sender.try_send(String::from("lost")),
+            Err(TrySendError::Disconnected(_))
+        ));
+    }
+
+    #[test]
+    fn try_send_wakes_receiver() {
+        let (mut sender, mut receiver) = sync_channel(1);
+
+        let t = std::thread::spawn(move || receiver.receive());
+
+        std::thread::sleep(Duration::from_millis(50));
+        sender.try_send(1).unwrap();
+        assert_eq!(t.join().unwrap(), Some(1));
+    }
+
+    #[test]
+    fn metrics_on_both_sides() {
+        let (mut sender, mut receiver) = sync_channel(4);
+        assert_eq!(sender.capacity(), Some(4));
+        assert_eq!(receiver.capacity(), Some(4));
+        assert!(sender.is_empty());
+
+        for i in 0..3 {
+            sender.send(i).unwrap();
+        }
+        assert_eq!(sender.len(), 3);
+        assert_eq!(receiver.len(), 3);
+
+        receiver.receive().unwrap();
+        assert_eq!(receiver.len(), 2);
+        assert_eq!(sender.total_sent(), 3);
+        assert_eq!(sender.total_received(), 1);
+        assert_eq!(receiver.high_water_mark(), 3);
+
+        receiver.receive().unwrap();
+        receiver.receive().unwrap();
+        assert!(receiver.is_empty());
+        // It only ever goes up.
+        assert_eq!(sender.high_water_mark(), 3);
+    }
+
+    #[test]
+    fn len_counts_buffered_values() {
+        let (mut sender, mut receiver) = channel();
+        for i in 0..5 {
+            sender.send(i).unwrap();
+        }
+
+        // An unbounded receive moves everything into the receiver's buffer, none of it is received yet though.
+        assert_eq!(receiver.receive(), Some(0));
+        assert_eq!(sender.len(), 4);
+        assert_eq!(receiver.receive_batch(2).count(), 2);
+        assert_eq!(sender.len(), 2);
+        assert_eq!(receiver.drain().count(), 2);
+        assert!(sender.is_empty());
+        assert_eq!(sender.total_received(), 5);
+    }
+
+    #[test]
+    fn counts_and_disconnection() {
+        let (sender, receiver) = channel::<()>();
+        assert_eq!(sender.sender_count(), 1);
+        assert_eq!(sender.receiver_count(), 1);
+
+        let other_sender = sender.clone();
+        let other_receiver = receiver.clone();
+        assert_eq!(receiver.sender_count(), 2);
+        assert_eq!(receiver.receiver_count(), 2);
+
+        drop(sender);
+        assert!(!receiver.is_disconnected());
+        drop(other_sender);
+        assert!(receiver.is_disconnected());
+
+        let (sender, receiver) = channel::<()>();
+        drop(receiver);
+        assert!(sender.is_disconnected());
+        drop(other_receiver);
+    }
+
+    #[test]
+    fn values_dropped_by_last_receiver_empty_the_channel() {
+        let (mut sender, mut receiver) = channel();
+        for i in 0..4 {
+            sender.send(i).unwrap();
+        }
+        // Two end up in the receiver's buffer, the rest stays queued.
+        receiver.receive().unwrap();
+        sender.send(4).unwrap();
+        assert_eq!(sender.len(), 4);
+
+        drop(receiver);
+        assert!(sender.is_empty());
+    }
+
+    #[test]
+    fn rendezvous_send_given_back_is_not_counted() {
+        let (mut sender, receiver) = sync_channel(0);
+
+        let t = std::thread::spawn(move || {
+            let result = sender.send(1);
+            (result, sender.total_sent())
+        });
+
+        std::thread::sleep(Duration::from_millis(50));
+        drop(receiver);
+        assert_eq!(t.join().unwrap(), (Err(SendError(1)), 0));
+    }
+
+    #[test]
+    fn panicking_sender_is_recorded() {
+        let (mut sender, mut receiver) = channel();
+        let mut other_sender = sender.clone();
+
+        let t = std::thread::spawn(move || {
+            sender.send(1).unwrap();
+            panic!("sender blew up");
+        });
+        assert!(t.join().is_err());
+        assert!(receiver.sender_panicked());
+
+        // The channel doesn't care, the value sent before the panic is still there and the other sender still works.
+        other_sender.send(2).unwrap();
+        drop(other_sender);
+        assert_eq!(receiver.iter().collect::<Vec<_>>(), vec![1, 2]);
+        assert!(receiver.sender_panicked());
+    }
+
+    #[test]
+    fn sender_dropped_normally_is_not_a_panic() {
+        let (sender, receiver) = channel::<()>();
+        std::thread::spawn(move || drop(sender)).join().unwrap();
+        assert!(receiver.is_disconnected());
+        assert!(!receiver.sender_panicked());
+    }
+
+    #[test]
+    fn panicking_sender_wakes_up_receiver() {
+        let (sender, mut receiver) = channel::<()>();
+
+        let t = std::thread::spawn(move || {
+            let _sender = sender;
+            std::thread::sleep(Duration::from_millis(50));
+            panic!("sender blew up");
+        });
+
+        assert_eq!(receiver.receive(), None);
+        assert!(receiver.sender_panicked());
+        assert!(t.join().is_err());
+    }
+
+    #[test]
+    fn channel_works_with_a_poisoned_lock() {
+        let (mut sender, mut receiver) = sync_channel(2);
+
+        // Nothing in the channel itself panics while holding the lock, so we poison it by hand, the way a panic under the lock would.
+        let shared = Arc::clone(&sender.shared);
+        let result = std::panic::catch_unwind(move || {
+            let _inner = shared.inner.lock().unwrap();
+            panic!("panicked while holding the lock");
+        });
+        assert!(result.is_err());
+        assert!(sender.shared.inner.is_poisoned());
+
+        sender.send(1).unwrap();
+        assert_eq!(sender.try_send(2), Ok(()));
+        assert_eq!(sender.len(), 2);
+        assert_eq!(receiver.receive(), Some(1));
+        assert_eq!(receiver.receive_timeout(Duration::from_millis(10)), Ok(2));
+
+        // Waiting on the condvars works too.
+        let t = std::thread::spawn(move || receiver.receive());
+        std::thread::sleep(Duration::from_millis(50));
+        sender.send(3).unwrap();
+        assert_eq!(t.join().unwrap(), Some(3));
+    }
+
+    fn receiver_wakers<T>(sender: &Sender<T>) -> usize {
+        sender.shared.inner.lock().unwrap().wakers.len()
+    }
 }
@@ -0,0 +1,391 @@
+// A channel where every message has a key and only the newest message per key is kept. Sending with a key that's already waiting in the
+// channel doesn't queue anything new, it swaps the value that's waiting for the new one. Good for things like cache invalidation,
+// if a key changed five times before anybody got around to it you only care about the last one.
+//
+// The replaced message keeps its spot in the line. Otherwise a key that's updated all the time would keep going to the back and never get
+// read. So the queue is the same VecDeque as in channels except it only holds the keys, the values sit in a HashMap next to it, and a key is
+// only ever in the queue once.
+//
+// Unbounded only. It never holds more messages than there are distinct keys, so it can't grow forever the way a plain channel can.
+//
+// K's Hash, Eq and Clone run under the lock, and they're user code that could panic. All of the locking goes through poison for that,
+// and everything under the lock is ordered so a panic in there leaves the queue and the map as they were. The next one to lock just carries on.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::Hash,
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    channels::{ReceiveTimeoutError, SendError, TryReceiveError},
+    poison::{lock, wait, wait_timeout},
+    receiver,
+};
+
+pub struct Sender<K, V> {
+    shared: Arc<Shared<K, V>>,
+}
+
+// Same as channels, no #[derive(Clone)] cause Arc is Clone no matter what K and V are.
+impl<K, V> Clone for Sender<K, V> {
+    fn clone(&self) -> Self {
+        let mut inner = lock(&self.shared.inner);
+        inner.senders += 1;
+        drop(inner);
+
+        Sender {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<K, V> Drop for Sender<K, V> {
+    fn drop(&mut self) {
+        let mut inner = lock(&self.shared.inner);
+        inner.senders -= 1;
+        let is_last_sender = inner.senders == 0;
+        drop(inner);
+
+        if is_last_sender {
+            self.shared.signal_data_sent.notify_all();
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone, V> Sender<K, V> {
+    // Never blocks. If a message with this key is still waiting its value is replaced and you get the old one back,
+    // the way HashMap::insert does it. Fails once all of the receivers are gone.
+    pub fn send(&mut self, key: K, value: V) -> Result<Option<V>, SendError<(K, V)>> {
+        let mut inner = lock(&self.shared.inner);
+
+        if inner.receivers == 0 {
+            return Err(SendError((key, value)));
+        }
+
+        // Only a key that's new to the channel goes into the queue, a replaced one stays where it was.
+        let replaced = match inner.values.get_mut(&key) {
+            Some(waiting) => Some(std::mem::replace(waiting, value)),
+            // The clone and the insert first, they're what could panic. By the time the key is queued it's in the map too.
+            None => {
+                let queued = key.clone();
+                inner.values.insert(key, value);
+                inner.queue.push_back(queued);
+                None
+            }
+        };
+        drop(inner);
+
+        // Nothing new to receive if we only replaced a value, whoever could take it has already been woken up for it.
+        if replaced.is_none() {
+            self.shared.signal_data_sent.notify_one();
+        }
+        Ok(replaced)
+    }
+}
+
+pub struct Receiver<K, V> {
+    shared: Arc<Shared<K, V>>,
+}
+
+impl<K, V> Clone for Receiver<K, V> {
+    fn clone(&self) -> Self {
+        let mut inner = lock(&self.shared.inner);
+        inner.receivers += 1;
+        drop(inner);
+
+        Receiver {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<K, V> Drop for Receiver<K, V> {
+    fn drop(&mut self) {
+        let mut inner = lock(&self.shared.inner);
+        inner.receivers -= 1;
+
+        // Nobody is going to read what's left, drop it outside the lock same as channels does.
+        let unread = if inner.receivers == 0 {
+            (
+                std::mem::take(&mut inner.queue),
+                std::mem::take(&mut inner.values),
+            )
+        } else {
+            (VecDeque::new(), HashMap::new())
+        };
+        drop(inner);
+        drop(unread);
+    }
+}
+
+impl<K: Eq + Hash, V> Receiver<K, V> {
+    // Blocks until there's a message and hands out the one whose key has been waiting the longest, with the newest value sent for it.
+    // None once all of the senders are gone and everything has been read.
+    pub fn receive(&mut self) -> Option<(K, V)> {
+        let mut inner = lock(&self.shared.inner);
+
+        loop {
+            if let Some(message) = inner.pop() {
+                return Some(message);
+            }
+
+            if inner.senders == 0 {
+                return None;
+            }
+
+            inner = wait(&self.shared.signal_data_sent, inner);
+        }
+    }
+
+    pub fn try_receive(&mut self) -> Result<(K, V), TryReceiveError> {
+        let mut inner = lock(&self.shared.inner);
+
+        if let Some(message) = inner.pop() {
+            return Ok(message);
+        }
+
+        if inner.senders == 0 {
+            Err(TryReceiveError::Disconnected)
+        } else {
+            Err(TryReceiveError::Empty)
+        }
+    }
+
+    pub fn receive_timeout(&mut self, timeout: Duration) -> Result<(K, V), ReceiveTimeoutError> {
+        receiver::receive_timeout(self, timeout, Self::receive, Self::receive_deadline)
+    }
+
+    pub fn receive_deadline(&mut self, deadline: Instant) -> Result<(K, V), ReceiveTimeoutError> {
+        let mut inner = lock(&self.shared.inner);
+
+        // Same loop as in channels, the queue is checked before the clock so a message that came in right as we timed out still gets picked up.
+        loop {
+            if let Some(message) = inner.pop() {
+                return Ok(message);
+            }
+
+            if inner.senders == 0 {
+                return Err(ReceiveTimeoutError::Disconnected);
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(ReceiveTimeoutError::Timeout);
+            }
+
+            inner = wait_timeout(&self.shared.signal_data_sent, inner, deadline - now);
+        }
+    }
+
+    // How many keys have a message waiting.
+    pub fn len(&self) -> usize {
+        lock(&self.shared.inner).queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+struct Inner<K, V> {
+    // Every key in here has exactly one value in values and the other way around.
+    queue: VecDeque<K>,
+    values: HashMap<K, V>,
+    senders: usize,
+    receivers: usize,
+}
+
+impl<K: Eq + Hash, V> Inner<K, V> {
+    // Out of the map first, that's where K's Hash and Eq run. If they panic the key is still queued and its value still there.
+    fn pop(&mut self) -> Option<(K, V)> {
+        let key = self.queue.front()?;
+        let message = self
+            .values
+            .remove_entry(key)
+            .expect("every queued key has a value");
+        self.queue.pop_front();
+        Some(message)
+    }
+}
+
+struct Shared<K, V> {
+    inner: Mutex<Inner<K, V>>,
+    // No signal_space_available, there's no capacity to wait on.
+    signal_data_sent: Condvar,
+}
+
+// Its convention to return the Sender first and then the Receiver.
+pub fn coalescing_channel<K, V>() -> (Sender<K, V>, Receiver<K, V>) {
+    let shared = Arc::new(Shared {
+        inner: Mutex::new(Inner {
+            queue: VecDeque::new(),
+            values: HashMap::new(),
+            senders: 1,
+            receivers: 1,
+        }),
+        signal_data_sent: Condvar::new(),
+    });
+
+    (
+        Sender {
+            shared: Arc::clone(&shared),
+        },
+        Receiver { shared },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn newest_value_per_key() {
+        let (mut sender, mut receiver) = coalescing_channel();
+        assert_eq!(sender.send("a", 1), Ok(None));
+        assert_eq!(sender.send("a", 2), Ok(Some(1)));
+        assert_eq!(sender.send("a", 3), Ok(Some(2)));
+        assert_eq!(receiver.len(), 1);
+
+        assert_eq!(receiver.receive(), Some(("a", 3)));
+        assert!(receiver.is_empty());
+    }
+
+    #[test]
+    fn replaced_value_keeps_its_position() {
+        let (mut sender, mut receiver) = coalescing_channel();
+        sender.send("a", 1).unwrap();
+        sender.send("b", 1).unwrap();
+        sender.send("c", 1).unwrap();
+        sender.send("a", 2).unwrap();
+
+        let got: Vec<_> = (0..3).map(|_| receiver.receive().unwrap()).collect();
+        assert_eq!(got, vec![("a", 2), ("b", 1), ("c", 1)]);
+    }
+
+    #[test]
+    fn key_can_be_sent_again_once_received() {
+        let (mut sender, mut receiver) = coalescing_channel();
+        sender.send("a", 1).unwrap();
+        sender.send("b", 1).unwrap();
+        assert_eq!(receiver.receive(), Some(("a", 1)));
+
+        // "a" isn't waiting anymore, so this one goes to the back.
+        assert_eq!(sender.send("a", 2), Ok(None));
+        assert_eq!(receiver.receive(), Some(("b", 1)));
+        assert_eq!(receiver.receive(), Some(("a", 2)));
+    }
+
+    #[test]
+    fn receive_waits_for_send() {
+        let (mut sender, mut receiver) = coalescing_channel();
+
+        let t = thread::spawn(move || receiver.receive());
+
+        thread::sleep(Duration::from_millis(50));
+        assert!(!t.is_finished());
+        sender.send(1, "one").unwrap();
+        assert_eq!(t.join().unwrap(), Some((1, "one")));
+    }
+
+    #[test]
+    fn disconnected_after_reading_everything() {
+        let (mut sender, mut receiver) = coalescing_channel();
+        sender.send(1, ()).unwrap();
+        drop(sender);
+
+        assert_eq!(receiver.try_receive(), Ok((1, ())));
+        assert_eq!(receiver.try_receive(), Err(TryReceiveError::Disconnected));
+        assert_eq!(receiver.receive(), None);
+    }
+
+    #[test]
+    fn send_fails_without_receivers() {
+        let (mut sender, receiver) = coalescing_channel();
+        drop(receiver);
+        assert_eq!(sender.send("a", 1), Err(SendError(("a", 1))));
+    }
+
+    #[test]
+    fn receive_timeout() {
+        let (mut sender, mut receiver) = coalescing_channel();
+        assert_eq!(
+            receiver.receive_timeout(Duration::from_millis(20)),
+            Err(ReceiveTimeoutError::Timeout)
+        );
+
+        sender.send("a", 1).unwrap();
+        assert_eq!(
+            receiver.receive_timeout(Duration::from_millis(20)),
+            Ok(("a", 1))
+        );
+
+        drop(sender);
+        assert_eq!(
+            receiver.receive_timeout(Duration::from_millis(20)),
+            Err(ReceiveTimeoutError::Disconnected)
+        );
+    }
+
+    #[test]
+    fn invalidations_from_many_senders() {
+        let (sender, mut receiver) = coalescing_channel::<u32, u32>();
+
+        // Every sender bumps the same ten keys over and over. Whatever got coalesced along the way, the last thing received for every key
+        // has to be the last version sent for it.
+        let senders: Vec<_> = (0..4)
+            .map(|_| {
+                let mut sender = sender.clone();
+                thread::spawn(move || {
+                    for version in 0..100 {
+                        for key in 0..10 {
+                            sender.send(key, version).unwrap();
+                        }
+                    }
+                })
+            })
+            .collect();
+        drop(sender);
+
+        let mut latest = HashMap::new();
+        while let Some((key, version)) = receiver.receive() {
+            latest.insert(key, version);
+        }
+        for t in senders {
+            t.join().unwrap();
+        }
+
+        assert_eq!(latest.len(), 10);
+        assert!(latest.values().all(|&version| version == 99));
+    }
+
+    #[test]
+    fn panicking_hash_leaves_the_channel_usable() {
+        // Refuses to be hashed when it's negative.
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        struct Key(i32);
+
+        impl Hash for Key {
+            fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+                assert!(self.0 >= 0, "negative key");
+                self.0.hash(state);
+            }
+        }
+
+        let (mut sender, mut receiver) = coalescing_channel();
+        sender.send(Key(1), "one").unwrap();
+
+        let mut other = sender.clone();
+        let t = thread::spawn(move || other.send(Key(-1), "minus one"));
+        assert!(t.join().is_err());
+        assert!(sender.shared.inner.is_poisoned());
+
+        // Poisoned but perfectly fine, the bad key never made it in.
+        assert_eq!(sender.send(Key(1), "uno"), Ok(Some("one")));
+        assert_eq!(receiver.len(), 1);
+        assert_eq!(receiver.receive(), Some((Key(1), "uno")));
+        assert_eq!(receiver.try_receive(), Err(TryReceiveError::Empty));
+    }
+}
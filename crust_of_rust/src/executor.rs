@@ -0,0 +1,235 @@
+// Just enough of an async runtime to drive the futures in this crate, channels::Receiver::receive_async mostly. No IO, no timers, no thread pool.
+//
+// Both of these work the same way as parking_and_condition_variables::parking over in rust-concurrency. The thread polls whatever it has,
+// parks when nothing can make progress, and the Waker handed to the futures is the producer side of that example, it unparks the thread.
+// An unpark that happens before the park isn't lost, the next park returns right away, so there's no window where a wake up can slip through.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    pin::{Pin, pin},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    task::{Context, Poll, Wake, Waker},
+    thread::{self, Thread},
+};
+
+// Wakes up the thread sitting in block_on.
+struct ThreadWaker {
+    thread: Thread,
+    // park can return spuriously, this is what tells us whether somebody actually woke us up.
+    woken: AtomicBool,
+}
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        // Release pairs with the Acquire in block_on, whatever the waker saw before waking is visible to the next poll.
+        self.woken.store(true, Ordering::Release);
+        self.thread.unpark();
+    }
+}
+
+// Runs a future to completion on the current thread, parking in between polls.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    // The future lives on our stack and never moves again, so pinning it here is fine.
+    let mut future = pin!(future);
+
+    let thread_waker = Arc::new(ThreadWaker {
+        thread: thread::current(),
+        woken: AtomicBool::new(false),
+    });
+    let waker = Waker::from(Arc::clone(&thread_waker));
+    let mut cx = Context::from_waker(&waker);
+
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+
+        // No point polling again before somebody wakes us.
+        while !thread_waker.woken.swap(false, Ordering::Acquire) {
+            thread::park();
+        }
+    }
+}
+
+// The ids of the tasks that were woken and want to be polled again, plus the thread that does the polling.
+struct ReadyQueue {
+    queue: Mutex<VecDeque<usize>>,
+    thread: Thread,
+}
+
+// Every task gets its own waker so the executor knows which one to poll instead of polling all of them.
+struct TaskWaker {
+    id: usize,
+    ready: Arc<ReadyQueue>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.ready.queue.lock().unwrap().push_back(self.id);
+        self.ready.thread.unpark();
+    }
+}
+
+// Single threaded, everything runs on the thread that created it. The tasks don't have to be Send for the same reason,
+// which in turn makes the Executor itself not Send, so it can't end up being run on some other thread than the one the wakers unpark.
+pub struct Executor {
+    tasks: HashMap<usize, Pin<Box<dyn Future<Output = ()>>>>,
+    next_id: usize,
+    ready: Arc<ReadyQueue>,
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Executor {
+    pub fn new() -> Self {
+        Executor {
+            tasks: HashMap::new(),
+            next_id: 0,
+            ready: Arc::new(ReadyQueue {
+                queue: Mutex::new(VecDeque::new()),
+                thread: thread::current(),
+            }),
+        }
+    }
+
+    // Nothing runs until run is called. Tasks don't return anything, send the result over a channel if you need it.
+    pub fn spawn(&mut self, future: impl Future<Output = ()> + 'static) {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.tasks.insert(id, Box::pin(future));
+        // Every task gets polled at least once, that's how it gets to hand out its waker in the first place.
+        self.ready.queue.lock().unwrap().push_back(id);
+    }
+
+    // Polls the tasks as they get woken up until every one of them is done. If a task waits for something that never happens, so does this.
+    pub fn run(&mut self) {
+        while !self.tasks.is_empty() {
+            // Not in the if let below, we don't want to hold the lock while polling, a task waking itself would deadlock.
+            let next = self.ready.queue.lock().unwrap().pop_front();
+            let Some(id) = next else {
+                // Spurious wake ups just go around the loop and find the queue still empty.
+                thread::park();
+                continue;
+            };
+
+            // The same task can get woken a couple of times before it's polled, or after it's already done. Polling it once more is harmless,
+            // a finished one is simply gone.
+            let Some(task) = self.tasks.get_mut(&id) else {
+                continue;
+            };
+
+            let waker = Waker::from(Arc::new(TaskWaker {
+                id,
+                ready: Arc::clone(&self.ready),
+            }));
+            if task
+                .as_mut()
+                .poll(&mut Context::from_waker(&waker))
+                .is_ready()
+            {
+                self.tasks.remove(&id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channels;
+    use std::{cell::RefCell, rc::Rc, time::Duration};
+
+    #[test]
+    fn block_on_ready_future() {
+        assert_eq!(block_on(async { 1 + 2 }), 3);
+    }
+
+    #[test]
+    fn block_on_waits_for_wake_up() {
+        let (mut sender, mut receiver) = channels::channel();
+
+        let t = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            sender.send(5).unwrap();
+        });
+
+        assert_eq!(block_on(receiver.receive_async()), Some(5));
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn executor_with_no_tasks_returns() {
+        Executor::new().run();
+    }
+
+    #[test]
+    fn executor_runs_tasks_as_they_get_woken() {
+        let mut executor = Executor::new();
+        // Not Send on purpose, the tasks never leave this thread.
+        let got = Rc::new(RefCell::new(Vec::new()));
+        let mut senders = Vec::new();
+
+        for task in 0..3 {
+            let (sender, mut receiver) = channels::channel();
+            senders.push(sender);
+            let got = Rc::clone(&got);
+            executor.spawn(async move {
+                while let Some(data) = receiver.receive_async().await {
+                    got.borrow_mut().push((task, data));
+                }
+            });
+        }
+
+        let t = thread::spawn(move || {
+            // Backwards so the order they finish in isn't just the order they were spawned in.
+            for (task, mut sender) in senders.into_iter().enumerate().rev() {
+                thread::sleep(Duration::from_millis(10));
+                sender.send(task * 10).unwrap();
+            }
+        });
+
+        executor.run();
+        t.join().unwrap();
+        assert_eq!(*got.borrow(), vec![(2, 20), (1, 10), (0, 0)]);
+    }
+
+    #[test]
+    fn tasks_talking_to_each_other() {
+        let mut executor = Executor::new();
+        let (mut sender, mut receiver) = channels::channel();
+        let (mut result_sender, mut result_receiver) = channels::channel();
+
+        // Spawned first, so it has to wait on the other one.
+        executor.spawn(async move {
+            let mut sum = 0;
+            while let Some(data) = receiver.receive_async().await {
+                sum += data;
+            }
+            result_sender.send(sum).unwrap();
+        });
+        executor.spawn(async move {
+            for i in 1..=4 {
+                sender.send(i).unwrap();
+            }
+        });
+
+        executor.run();
+        assert_eq!(result_receiver.try_receive(), Ok(10));
+    }
+}
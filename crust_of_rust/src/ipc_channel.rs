@@ -0,0 +1,875 @@
+// A channel between processes instead of threads. Everything the other channels keep in an Arc lives in a chunk of shared memory here,
+// so nothing in it can point anywhere, the other process has its own address space and a pointer from ours means nothing to it.
+// That's why it only carries bytes. Either length prefixed byte messages or Pod values, which are copied in and out byte for byte.
+//
+// The memory comes from memfd_create, an anonymous file that only exists in RAM, and both processes mmap it. A fork keeps the mapping,
+// and the fd can be handed to a process that wasn't forked from us as well. In it sits a header and a byte ring buffer. It's single producer,
+// single consumer like spsc: the sender is the only one moving tail, the receiver is the only one moving head.
+//
+// Blocking can't use a Condvar or thread::park, they only work within a process. Futexes can. futex_wait sleeps as long as a 32 bit word
+// in the shared memory still holds the value we expect, and futex_wake wakes up whoever sleeps on that word, no matter the process.
+//
+// Only Linux, and there's no libc crate, so the few functions we need are declared by hand at the bottom.
+
+use std::{
+    ffi::{c_char, c_int, c_long, c_void},
+    fs::File,
+    io,
+    mem::{self, MaybeUninit},
+    os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd},
+    ptr,
+    sync::atomic::{AtomicU32, Ordering, fence},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    channels::{ReceiveTimeoutError, SendError, TryReceiveError},
+    receiver,
+};
+
+/// Plain old data. Anything where every bit pattern is a valid value and that holds no pointers, references or handles, so the bytes mean
+/// the same thing in the other process. Copy alone isn't enough, &T is Copy.
+///
+/// # Safety
+///
+/// Implementing it promises exactly that. repr(C) structs made only of Pod fields (and without padding) qualify.
+pub unsafe trait Pod: Copy + 'static {}
+
+macro_rules! impl_pod {
+    ($($t:ty),*) => {
+        $(unsafe impl Pod for $t {})*
+    };
+}
+
+impl_pod!(
+    u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64
+);
+
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
+// Sits at the start of the shared memory. Only atomics, repr(C) so both processes agree on where everything is,
+// in case they weren't built from the exact same code.
+#[repr(C)]
+struct Header {
+    // Positions in bytes. They never get reduced, they just wrap around at u32::MAX, and the spot in the ring is the position modulo
+    // the capacity. That's why the capacity is a power of two, it divides 2^32, so the wrap doesn't skip a beat.
+    head: AtomicU32,
+    tail: AtomicU32,
+    // Futex words, the ones the two sides sleep on. data_sequence goes up with every send and when the sender goes away,
+    // space_sequence with every receive and when the receiver goes away. A side that read one and then waits on it can't miss a change,
+    // futex_wait refuses to sleep if the word doesn't hold what it read anymore.
+    data_sequence: AtomicU32,
+    space_sequence: AtomicU32,
+    // Set right before sleeping, so the other side only makes the futex_wake syscall when somebody actually sleeps.
+    receiver_waiting: AtomicU32,
+    sender_waiting: AtomicU32,
+    // 1 once that side was dropped. A process that gets killed never drops anything though, so the other side keeps waiting in that case.
+    sender_closed: AtomicU32,
+    receiver_closed: AtomicU32,
+    // Only there so from_fd can tell it's looking at one of ours. Atomic like the rest, the other side can write it while we read it.
+    capacity: AtomicU32,
+}
+
+// The ring starts at the next cache line, so the header and the data don't share one.
+const DATA_OFFSET: usize = 64;
+const _: () = assert!(mem::size_of::<Header>() <= DATA_OFFSET);
+
+// Every message starts with its length.
+const LENGTH_PREFIX: u32 = mem::size_of::<u32>() as u32;
+
+// One mapping of the shared memory. Each of IpcChannel, Sender and Receiver owns one, and unmaps it when dropped.
+//
+// Whoever has the fd can write anything they like into the memory, with a plain write on the fd even, so nothing in it can be trusted
+// to keep us inside the mapping. They can't change its size though, the memfd is sealed, see IpcChannel::new. Shrinking the file under
+// a mapping makes every access past the new end a SIGBUS. The capacity we go by is the one that comes from the size of the mapping, not the one in the header,
+// and head, tail and the length prefixes get checked against it before anything is copied.
+struct Region {
+    ptr: *mut u8,
+    len: usize,
+    capacity: u32,
+}
+
+// The memory is shared with another process anyways, another thread is no different. Everything in it is synchronized through the header.
+unsafe impl Send for Region {}
+
+impl Region {
+    fn map(fd: BorrowedFd<'_>, len: usize) -> io::Result<Region> {
+        // SAFETY: Plain mmap of a file we have open, the kernel checks everything else.
+        let ptr = unsafe {
+            mmap(
+                ptr::null_mut(),
+                len,
+                PROT_READ | PROT_WRITE,
+                MAP_SHARED,
+                fd.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Region {
+            ptr: ptr.cast(),
+            len,
+            capacity: (len - DATA_OFFSET) as u32,
+        })
+    }
+
+    fn header(&self) -> &Header {
+        // SAFETY: The mapping is at least DATA_OFFSET bytes, page aligned, and a zeroed Header is a valid one.
+        unsafe { &*self.ptr.cast::<Header>() }
+    }
+
+    fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    // How many bytes are in the ring between head and tail, None if that's more than fits, then somebody scribbled over the header.
+    fn used(&self, head: u32, tail: u32) -> Option<u32> {
+        let used = tail.wrapping_sub(head);
+        (used <= self.capacity).then_some(used)
+    }
+
+    // Copies bytes into the ring starting at position, wrapping around the end if need be.
+    // SAFETY: Only the sender may write, and only to bytes between tail and head + capacity, the receiver doesn't read those.
+    unsafe fn write(&self, position: u32, bytes: &[u8]) {
+        let offset = (position & (self.capacity() - 1)) as usize;
+        let first = bytes.len().min(self.capacity() as usize - offset);
+        unsafe {
+            let data = self.ptr.add(DATA_OFFSET);
+            ptr::copy_nonoverlapping(bytes.as_ptr(), data.add(offset), first);
+            ptr::copy_nonoverlapping(bytes.as_ptr().add(first), data, bytes.len() - first);
+        }
+    }
+
+    // The other way around.
+    // SAFETY: Only the receiver may read, and only bytes between head and tail, the sender doesn't touch those.
+    unsafe fn read(&self, position: u32, out: *mut u8, len: usize) {
+        let offset = (position & (self.capacity() - 1)) as usize;
+        let first = len.min(self.capacity() as usize - offset);
+        unsafe {
+            let data = self.ptr.add(DATA_OFFSET);
+            ptr::copy_nonoverlapping(data.add(offset), out, first);
+            ptr::copy_nonoverlapping(data, out.add(first), len - first);
+        }
+    }
+}
+
+impl Drop for Region {
+    fn drop(&mut self) {
+        // SAFETY: We mapped it and nothing borrows from it anymore, everything that did borrowed from us.
+        unsafe { munmap(self.ptr.cast(), self.len) };
+    }
+}
+
+// The channel before it's split up. Fork with it (or pass the fd along), then have one process turn it into the Sender and the other into
+// the Receiver. It can't hand out both right away like the other channels do, after a fork both processes would have both halves,
+// and the half a process doesn't use would close the channel when it's dropped.
+pub struct IpcChannel {
+    region: Region,
+    fd: OwnedFd,
+}
+
+impl IpcChannel {
+    // The ring holds capacity bytes, rounded up to a power of two. Every message takes up 4 bytes for its length on top of what's in it.
+    pub fn new(capacity: usize) -> io::Result<IpcChannel> {
+        assert!(
+            capacity > LENGTH_PREFIX as usize && capacity <= 1 << 31,
+            "ipc channel capacity must be more than 4 bytes and at most 2^31"
+        );
+        let capacity = capacity.next_power_of_two();
+
+        // CLOEXEC so it doesn't leak into every program we spawn. A helper that's meant to have it can get it over a unix socket.
+        // ALLOW_SEALING so we can lock its size down below.
+        // SAFETY: The name is a nul terminated string, the kernel only uses it for /proc.
+        let fd = unsafe { memfd_create(c"ipc_channel".as_ptr(), MFD_CLOEXEC | MFD_ALLOW_SEALING) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: memfd_create just gave it to us and nobody else has it.
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        // A fresh memfd is empty, this gives it its size. It comes back zeroed, which is exactly the header we want, apart from the capacity.
+        File::from(fd.try_clone()?).set_len((DATA_OFFSET + capacity) as u64)?;
+        // From here on nobody can make it bigger or smaller, not even us, and the last seal means nobody can take the others off again.
+        // SAFETY: fcntl on an fd we own, with an int argument like F_ADD_SEALS expects.
+        if unsafe { fcntl(fd.as_raw_fd(), F_ADD_SEALS, SEALS) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let region = Region::map(fd.as_fd(), DATA_OFFSET + capacity)?;
+        region
+            .header()
+            .capacity
+            .store(capacity as u32, Ordering::Relaxed);
+
+        Ok(IpcChannel { region, fd })
+    }
+
+    // Opens a channel from an fd another process created with new and passed over, through a unix socket or by inheriting it.
+    pub fn from_fd(fd: OwnedFd) -> io::Result<IpcChannel> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "not an ipc channel");
+
+        // Without the seals whoever else has the fd could truncate it under our mapping. Anything that isn't a memfd fails F_GET_SEALS outright.
+        // SAFETY: fcntl on an fd we own, F_GET_SEALS takes no argument.
+        let seals = unsafe { fcntl(fd.as_raw_fd(), F_GET_SEALS) };
+        if seals < 0 || seals & SEALS != SEALS {
+            return Err(invalid());
+        }
+
+        // Sealed, so this is the size for good.
+        let len = File::from(fd.try_clone()?).metadata()?.len() as usize;
+        if len <= DATA_OFFSET {
+            return Err(invalid());
+        }
+
+        let capacity = len - DATA_OFFSET;
+        if !capacity.is_power_of_two() || capacity <= LENGTH_PREFIX as usize || capacity > 1 << 31 {
+            return Err(invalid());
+        }
+
+        let region = Region::map(fd.as_fd(), len)?;
+        if region.header().capacity.load(Ordering::Relaxed) as usize != capacity {
+            return Err(invalid());
+        }
+
+        Ok(IpcChannel { region, fd })
+    }
+
+    // The ring's size in bytes, after rounding up.
+    pub fn capacity(&self) -> usize {
+        self.region.capacity() as usize
+    }
+
+    pub fn into_sender(self) -> Sender {
+        Sender {
+            region: self.region,
+        }
+    }
+
+    pub fn into_receiver(self) -> Receiver {
+        Receiver {
+            region: self.region,
+        }
+    }
+}
+
+impl AsFd for IpcChannel {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+// Not Clone, there's exactly one sender. Same goes for the receiver.
+pub struct Sender {
+    region: Region,
+}
+
+impl Drop for Sender {
+    fn drop(&mut self) {
+        let header = self.region.header();
+        // Release so a receiver that sees it also sees everything we sent before it.
+        header.sender_closed.store(1, Ordering::Release);
+        notify(&header.data_sequence, &header.receiver_waiting);
+    }
+}
+
+impl Sender {
+    // Blocks while there's no room for it. Fails once the receiver is gone, and hands back what it got, same as channels.
+    // A message that's bigger than the whole ring could never be sent, that panics. A header that makes no sense (the receiver's head
+    // is further away from our tail than the ring is big) counts as the receiver being gone, there's no telling where it's safe to write.
+    pub fn send<'a>(&mut self, message: &'a [u8]) -> Result<(), SendError<&'a [u8]>> {
+        let capacity = self.region.capacity();
+        assert!(
+            message.len() <= (capacity - LENGTH_PREFIX) as usize,
+            "message doesn't fit in the ipc channel"
+        );
+        let needed = LENGTH_PREFIX + message.len() as u32;
+        let header = self.region.header();
+
+        loop {
+            // Read before looking at anything, whatever the receiver does after this changes it and the wait below won't sleep.
+            let sequence = header.space_sequence.load(Ordering::Acquire);
+
+            if header.receiver_closed.load(Ordering::Acquire) == 1 {
+                return Err(SendError(message));
+            }
+
+            // Acquire so we don't overwrite bytes the receiver is still reading.
+            let head = header.head.load(Ordering::Acquire);
+            let tail = header.tail.load(Ordering::Relaxed);
+            let Some(used) = self.region.used(head, tail) else {
+                return Err(SendError(message));
+            };
+            if capacity - used >= needed {
+                // SAFETY: There's room between tail and head + capacity, and we're the only sender.
+                unsafe {
+                    self.region
+                        .write(tail, &(message.len() as u32).to_ne_bytes());
+                    self.region.write(tail.wrapping_add(LENGTH_PREFIX), message);
+                }
+                // Release so the receiver sees the bytes once it sees the new tail.
+                header
+                    .tail
+                    .store(tail.wrapping_add(needed), Ordering::Release);
+                notify(&header.data_sequence, &header.receiver_waiting);
+                return Ok(());
+            }
+
+            wait(
+                &header.space_sequence,
+                sequence,
+                &header.sender_waiting,
+                None,
+            );
+        }
+    }
+
+    // Sends the bytes of value. The receiver gets it back with receive_value.
+    pub fn send_value<T: Pod>(&mut self, value: T) -> Result<(), SendError<T>> {
+        // SAFETY: T is Pod, so looking at its bytes is fine.
+        let bytes = unsafe {
+            std::slice::from_raw_parts(ptr::from_ref(&value).cast::<u8>(), mem::size_of::<T>())
+        };
+        self.send(bytes).map_err(|_| SendError(value))
+    }
+}
+
+pub struct Receiver {
+    region: Region,
+}
+
+impl Drop for Receiver {
+    fn drop(&mut self) {
+        let header = self.region.header();
+        header.receiver_closed.store(1, Ordering::Release);
+        notify(&header.space_sequence, &header.sender_waiting);
+    }
+}
+
+impl Receiver {
+    // Blocks until there's a message. None once the sender is gone and everything it sent has been read.
+    pub fn receive(&mut self) -> Option<Vec<u8>> {
+        self.receive_until(None, read_bytes).ok()
+    }
+
+    pub fn try_receive(&mut self) -> Result<Vec<u8>, TryReceiveError> {
+        self.take(read_bytes)
+    }
+
+    pub fn receive_timeout(&mut self, timeout: Duration) -> Result<Vec<u8>, ReceiveTimeoutError> {
+        receiver::receive_timeout(self, timeout, Self::receive, Self::receive_deadline)
+    }
+
+    pub fn receive_deadline(&mut self, deadline: Instant) -> Result<Vec<u8>, ReceiveTimeoutError> {
+        self.receive_until(Some(deadline), read_bytes)
+    }
+
+    // Counterpart to send_value. Panics if the message isn't the size of a T, then it wasn't sent as one.
+    pub fn receive_value<T: Pod>(&mut self) -> Option<T> {
+        self.receive_until(None, |region, position, len| {
+            assert_eq!(
+                len,
+                mem::size_of::<T>(),
+                "message is not a {}",
+                std::any::type_name::<T>()
+            );
+            let mut value = MaybeUninit::<T>::uninit();
+            // SAFETY: The caller has the bytes between position and position + len to itself, and any bytes make a valid T, it's Pod.
+            unsafe {
+                region.read(position, value.as_mut_ptr().cast(), len);
+                value.assume_init()
+            }
+        })
+        .ok()
+    }
+
+    fn receive_until<R>(
+        &self,
+        deadline: Option<Instant>,
+        mut read: impl FnMut(&Region, u32, usize) -> R,
+    ) -> Result<R, ReceiveTimeoutError> {
+        let header = self.region.header();
+
+        loop {
+            // Same as in send, read before we look so we can't sleep through a send that happens in between.
+            let sequence = header.data_sequence.load(Ordering::Acquire);
+
+            match self.take(&mut read) {
+                Ok(data) => return Ok(data),
+                Err(TryReceiveError::Disconnected) => {
+                    return Err(ReceiveTimeoutError::Disconnected);
+                }
+                Err(TryReceiveError::Empty) => {}
+            }
+
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Err(ReceiveTimeoutError::Timeout);
+            }
+
+            wait(
+                &header.data_sequence,
+                sequence,
+                &header.receiver_waiting,
+                deadline,
+            );
+        }
+    }
+
+    // Takes the next message out of the ring, read gets the region, where the message starts and how long it is.
+    // If head, tail or the length prefix point outside of what the sender could have written, somebody other than the sender
+    // wrote to the memory. Nothing in there can be trusted after that, so it counts as disconnected and nothing gets read.
+    fn take<R>(&self, read: impl FnOnce(&Region, u32, usize) -> R) -> Result<R, TryReceiveError> {
+        let header = self.region.header();
+
+        // closed before tail. The sender closes after its last send, so if we see it closed we're sure to see that send in tail as well.
+        let closed = header.sender_closed.load(Ordering::Acquire) == 1;
+        let head = header.head.load(Ordering::Relaxed);
+        // Acquire so we see the bytes the sender wrote before moving tail.
+        let tail = header.tail.load(Ordering::Acquire);
+
+        let Some(used) = self.region.used(head, tail) else {
+            return Err(TryReceiveError::Disconnected);
+        };
+        if used == 0 {
+            return Err(if closed {
+                TryReceiveError::Disconnected
+            } else {
+                TryReceiveError::Empty
+            });
+        }
+
+        let mut prefix = [0; LENGTH_PREFIX as usize];
+        // SAFETY: Everything between head and tail was written by the sender and it won't touch it until we move head past it.
+        unsafe { self.region.read(head, prefix.as_mut_ptr(), prefix.len()) };
+        let len = u32::from_ne_bytes(prefix);
+        // A whole message, prefix included, has to be between head and tail. u64 so a huge len can't wrap around and look small.
+        if used < LENGTH_PREFIX || u64::from(LENGTH_PREFIX) + u64::from(len) > u64::from(used) {
+            return Err(TryReceiveError::Disconnected);
+        }
+        let data = read(&self.region, head.wrapping_add(LENGTH_PREFIX), len as usize);
+
+        // Release so the sender only reuses the bytes after we're done reading them.
+        header
+            .head
+            .store(head.wrapping_add(LENGTH_PREFIX + len), Ordering::Release);
+        notify(&header.space_sequence, &header.sender_waiting);
+        Ok(data)
+    }
+}
+
+fn read_bytes(region: &Region, position: u32, len: usize) -> Vec<u8> {
+    let mut message = Vec::with_capacity(len);
+    // SAFETY: Same as in take, and the Vec has room for len bytes, which we all write before setting the length.
+    unsafe {
+        region.read(position, message.as_mut_ptr(), len);
+        message.set_len(len);
+    }
+    message
+}
+
+// Lets the other side know that sequence changed. Same dance as wake_receiver in lock_free_channel.
+fn notify(sequence: &AtomicU32, waiting: &AtomicU32) {
+    sequence.fetch_add(1, Ordering::Release);
+    // Pairs with the fence in wait. Either the other side's futex_wait sees the new sequence and doesn't sleep, or we see that it's waiting.
+    fence(Ordering::SeqCst);
+    if waiting.load(Ordering::Relaxed) == 1 {
+        // SAFETY: sequence lives in the shared memory and outlives the call.
+        unsafe { futex(sequence, FUTEX_WAKE, 1, ptr::null()) };
+    }
+}
+
+// Sleeps until sequence isn't expected anymore, the deadline passes, or for no reason at all (signals and the like). The caller loops either way.
+fn wait(sequence: &AtomicU32, expected: u32, waiting: &AtomicU32, deadline: Option<Instant>) {
+    let timeout = match deadline {
+        Some(deadline) => {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            Some(Timespec {
+                seconds: remaining.as_secs() as c_long,
+                nanoseconds: remaining.subsec_nanos() as c_long,
+            })
+        }
+        None => None,
+    };
+
+    waiting.store(1, Ordering::Relaxed);
+    fence(Ordering::SeqCst);
+    let timeout = timeout.as_ref().map_or(ptr::null(), ptr::from_ref);
+    // SAFETY: Same as in notify, and the timeout outlives the call.
+    unsafe { futex(sequence, FUTEX_WAIT, expected, timeout) };
+    waiting.store(0, Ordering::Relaxed);
+}
+
+// What the kernel calls struct timespec, both fields are a long on 64 bit Linux.
+#[repr(C)]
+struct Timespec {
+    seconds: c_long,
+    nanoseconds: c_long,
+}
+
+// The futex syscall has no wrapper in libc, it goes through syscall with its number. Its return value doesn't matter to us, woken up,
+// timed out, interrupted or the value had already changed, the callers check the state again either way.
+unsafe fn futex(word: &AtomicU32, op: c_int, value: u32, timeout: *const Timespec) {
+    unsafe {
+        syscall(
+            SYS_FUTEX,
+            word.as_ptr(),
+            op,
+            value,
+            timeout,
+            ptr::null::<u32>(),
+            0u32,
+        )
+    };
+}
+
+// No _PRIVATE variants, those only work within one process.
+const FUTEX_WAIT: c_int = 0;
+const FUTEX_WAKE: c_int = 1;
+
+#[cfg(target_arch = "x86_64")]
+const SYS_FUTEX: c_long = 202;
+#[cfg(target_arch = "aarch64")]
+const SYS_FUTEX: c_long = 98;
+
+const MFD_CLOEXEC: u32 = 1;
+const MFD_ALLOW_SEALING: u32 = 2;
+const F_ADD_SEALS: c_int = 1033;
+const F_GET_SEALS: c_int = 1034;
+const F_SEAL_SEAL: c_int = 1;
+const F_SEAL_SHRINK: c_int = 2;
+const F_SEAL_GROW: c_int = 4;
+// Every channel's memfd has all three, from_fd won't take one that doesn't.
+const SEALS: c_int = F_SEAL_SHRINK | F_SEAL_GROW | F_SEAL_SEAL;
+const PROT_READ: c_int = 1;
+const PROT_WRITE: c_int = 2;
+const MAP_SHARED: c_int = 1;
+const MAP_FAILED: *mut c_void = usize::MAX as *mut c_void;
+
+unsafe extern "C" {
+    fn memfd_create(name: *const c_char, flags: u32) -> c_int;
+    fn mmap(
+        addr: *mut c_void,
+        len: usize,
+        prot: c_int,
+        flags: c_int,
+        fd: c_int,
+        offset: i64,
+    ) -> *mut c_void;
+    fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    fn fcntl(fd: c_int, cmd: c_int, ...) -> c_int;
+    fn syscall(number: c_long, ...) -> c_long;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    unsafe extern "C" {
+        fn fork() -> c_int;
+        fn waitpid(pid: c_int, status: *mut c_int, options: c_int) -> c_int;
+        fn _exit(status: c_int) -> !;
+    }
+
+    // Both ends in this process, the second one goes through from_fd like it would in a process that was handed the fd.
+    fn pair(capacity: usize) -> (Sender, Receiver) {
+        let channel = IpcChannel::new(capacity).unwrap();
+        let receiver = IpcChannel::from_fd(channel.as_fd().try_clone_to_owned().unwrap())
+            .unwrap()
+            .into_receiver();
+        (channel.into_sender(), receiver)
+    }
+
+    // The child of a fork only gets the thread that called it. If any other thread of the test runner held a lock (the allocator's, say)
+    // it stays locked in there forever. So the child can't allocate, and can't panic either, panicking allocates. It only gets to use
+    // the channel and tell us how it went through its exit status. _exit so it doesn't run anything of the parent's on the way out.
+    fn exit_child(ok: bool) -> ! {
+        unsafe { _exit(if ok { 0 } else { 1 }) }
+    }
+
+    fn wait_for_child(pid: c_int) -> c_int {
+        let mut status = 0;
+        // SAFETY: It's our child and status is a valid place to write to.
+        assert_eq!(unsafe { waitpid(pid, &mut status, 0) }, pid);
+        status
+    }
+
+    #[test]
+    fn send_then_receive() {
+        let (mut sender, mut receiver) = pair(64);
+
+        sender.send(b"hello").unwrap();
+        sender.send(b"").unwrap();
+        sender.send_value(42u64).unwrap();
+
+        assert_eq!(receiver.receive().as_deref(), Some(&b"hello"[..]));
+        assert_eq!(receiver.try_receive().as_deref(), Ok(&b""[..]));
+        assert_eq!(receiver.receive_value::<u64>(), Some(42));
+        assert_eq!(receiver.try_receive(), Err(TryReceiveError::Empty));
+    }
+
+    #[test]
+    fn capacity_rounds_up() {
+        assert_eq!(IpcChannel::new(100).unwrap().capacity(), 128);
+        assert_eq!(IpcChannel::new(64).unwrap().capacity(), 64);
+    }
+
+    #[test]
+    fn messages_wrap_around_the_ring() {
+        let (mut sender, mut receiver) = pair(16);
+
+        // 4 + 7 bytes don't divide 16, so sooner or later both the prefix and the message get split at the end of the ring.
+        for i in 0..50u8 {
+            let message = [i; 7];
+            sender.send(&message).unwrap();
+            assert_eq!(receiver.receive(), Some(message.to_vec()));
+        }
+    }
+
+    #[test]
+    fn send_blocks_until_there_is_room() {
+        let (mut sender, mut receiver) = pair(16);
+
+        sender.send_value(1u64).unwrap();
+        let t = thread::spawn(move || sender.send_value(2u64));
+
+        thread::sleep(Duration::from_millis(50));
+        assert!(!t.is_finished());
+        assert_eq!(receiver.receive_value::<u64>(), Some(1));
+        assert_eq!(t.join().unwrap(), Ok(()));
+        assert_eq!(receiver.receive_value::<u64>(), Some(2));
+    }
+
+    #[test]
+    fn receive_waits_for_send() {
+        let (mut sender, mut receiver) = pair(64);
+
+        let t = thread::spawn(move || receiver.receive());
+
+        thread::sleep(Duration::from_millis(50));
+        assert!(!t.is_finished());
+        sender.send(b"wake up").unwrap();
+        assert_eq!(t.join().unwrap().as_deref(), Some(&b"wake up"[..]));
+    }
+
+    #[test]
+    fn disconnection_both_ways() {
+        let (mut sender, mut receiver) = pair(64);
+        sender.send(b"last").unwrap();
+        drop(sender);
+        // What was sent before the sender left still arrives.
+        assert_eq!(receiver.receive().as_deref(), Some(&b"last"[..]));
+        assert_eq!(receiver.receive(), None);
+        assert_eq!(
+            receiver.receive_timeout(Duration::from_millis(10)),
+            Err(ReceiveTimeoutError::Disconnected)
+        );
+
+        let (mut sender, receiver) = pair(64);
+        drop(receiver);
+        assert_eq!(sender.send(b"lost"), Err(SendError(&b"lost"[..])));
+    }
+
+    #[test]
+    fn dropping_sender_wakes_up_receiver() {
+        let (sender, mut receiver) = pair(64);
+
+        let t = thread::spawn(move || receiver.receive());
+
+        thread::sleep(Duration::from_millis(50));
+        drop(sender);
+        assert_eq!(t.join().unwrap(), None);
+    }
+
+    #[test]
+    fn receive_timeout() {
+        let (mut sender, mut receiver) = pair(64);
+        assert_eq!(
+            receiver.receive_timeout(Duration::from_millis(20)),
+            Err(ReceiveTimeoutError::Timeout)
+        );
+
+        sender.send(b"in time").unwrap();
+        assert_eq!(
+            receiver
+                .receive_timeout(Duration::from_millis(20))
+                .as_deref(),
+            Ok(&b"in time"[..])
+        );
+    }
+
+    #[test]
+    fn from_fd_rejects_other_files() {
+        let file = File::open("/proc/self/status").unwrap();
+        assert!(IpcChannel::from_fd(file.into()).is_err());
+    }
+
+    #[test]
+    fn memory_cant_be_resized() {
+        let channel = IpcChannel::new(64).unwrap();
+        let file = File::from(channel.as_fd().try_clone_to_owned().unwrap());
+
+        // Truncating it under the mapping is what would have turned the next access into a SIGBUS.
+        assert!(file.set_len(0).is_err());
+        assert!(file.set_len(1 << 20).is_err());
+
+        let (mut sender, mut receiver) = (
+            IpcChannel::from_fd(file.into()).unwrap().into_sender(),
+            channel.into_receiver(),
+        );
+        sender.send(b"still here").unwrap();
+        assert_eq!(receiver.receive().as_deref(), Some(&b"still here"[..]));
+    }
+
+    #[test]
+    fn from_fd_rejects_unsealed_memory() {
+        // Looks exactly like a channel's memory, only without the seals.
+        // SAFETY: Same as in IpcChannel::new.
+        let fd = unsafe { memfd_create(c"not_sealed".as_ptr(), MFD_CLOEXEC) };
+        assert!(fd >= 0);
+        // SAFETY: memfd_create just gave it to us.
+        let file = File::from(unsafe { OwnedFd::from_raw_fd(fd) });
+        file.set_len((DATA_OFFSET + 64) as u64).unwrap();
+        std::os::unix::fs::FileExt::write_all_at(
+            &file,
+            &64u32.to_ne_bytes(),
+            mem::offset_of!(Header, capacity) as u64,
+        )
+        .unwrap();
+
+        let error = IpcChannel::from_fd(file.into()).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn corrupted_header_is_not_trusted() {
+        use std::os::unix::fs::FileExt;
+
+        // Plain writes through the fd, anybody who has it can do the same.
+        let channel = IpcChannel::new(64).unwrap();
+        let file = File::from(channel.as_fd().try_clone_to_owned().unwrap());
+        let (mut sender, mut receiver) = (
+            IpcChannel::from_fd(file.try_clone().unwrap().into())
+                .unwrap()
+                .into_sender(),
+            channel.into_receiver(),
+        );
+        let head = mem::offset_of!(Header, head) as u64;
+        let tail = mem::offset_of!(Header, tail) as u64;
+
+        // One 8 byte message whose length prefix claims 256 MiB, way past the end of the mapping.
+        file.write_all_at(&8u32.to_ne_bytes(), tail).unwrap();
+        file.write_all_at(&(256u32 << 20).to_ne_bytes(), DATA_OFFSET as u64)
+            .unwrap();
+        assert_eq!(receiver.try_receive(), Err(TryReceiveError::Disconnected));
+        assert_eq!(receiver.receive(), None);
+
+        // Tail further ahead of head than the ring is big. Neither side can make sense of that.
+        file.write_all_at(&1000u32.to_ne_bytes(), tail).unwrap();
+        assert_eq!(receiver.try_receive(), Err(TryReceiveError::Disconnected));
+        assert_eq!(sender.send(b"hi"), Err(SendError(&b"hi"[..])));
+
+        // Head ahead of tail, which would make the sender think there's more than a whole ring of room.
+        file.write_all_at(&0u32.to_ne_bytes(), tail).unwrap();
+        file.write_all_at(&8u32.to_ne_bytes(), head).unwrap();
+        assert_eq!(sender.send(b"hi"), Err(SendError(&b"hi"[..])));
+
+        // The capacity in the header isn't used at all once the channel is open.
+        file.write_all_at(&0u32.to_ne_bytes(), head).unwrap();
+        file.write_all_at(
+            &(1u32 << 31).to_ne_bytes(),
+            mem::offset_of!(Header, capacity) as u64,
+        )
+        .unwrap();
+        sender.send(b"still fine").unwrap();
+        assert_eq!(receiver.receive().as_deref(), Some(&b"still fine"[..]));
+    }
+
+    #[test]
+    fn values_from_a_child_process() {
+        const COUNT: u64 = 10_000;
+        // Small on purpose, the child has to wait on the parent over and over.
+        let channel = IpcChannel::new(64).unwrap();
+
+        // SAFETY: The child sticks to what's fine after a fork, see exit_child.
+        let pid = unsafe { fork() };
+        assert!(pid >= 0, "fork failed");
+        if pid == 0 {
+            // The fork gave us our own copy of channel, the parent goes on with its own.
+            let mut sender = channel.into_sender();
+            let ok = (0..COUNT).all(|i| sender.send_value(i).is_ok());
+            // _exit skips destructors, the receiver has to find out we're done.
+            drop(sender);
+            exit_child(ok);
+        }
+
+        let mut receiver = channel.into_receiver();
+        let mut expected = 0;
+        while let Some(value) = receiver.receive_value::<u64>() {
+            assert_eq!(value, expected);
+            expected += 1;
+        }
+        assert_eq!(expected, COUNT);
+        assert_eq!(wait_for_child(pid), 0);
+    }
+
+    #[test]
+    fn bytes_both_ways_between_processes() {
+        let requests = IpcChannel::new(256).unwrap();
+        let responses = IpcChannel::new(256).unwrap();
+
+        // SAFETY: Same as above.
+        let pid = unsafe { fork() };
+        assert!(pid >= 0, "fork failed");
+        if pid == 0 {
+            // An echo server that shouts back. Fixed size buffer on the stack, no allocating in here.
+            let receiver = requests.into_receiver();
+            let mut sender = responses.into_sender();
+            let mut buffer = [0u8; 32];
+            let ok = loop {
+                let received = receiver.receive_until(None, |region, position, len| {
+                    let len = len.min(buffer.len());
+                    // SAFETY: Same as in read_bytes, just into our buffer.
+                    unsafe { region.read(position, buffer.as_mut_ptr(), len) };
+                    len
+                });
+                let Ok(len) = received else {
+                    break true;
+                };
+                buffer[..len].make_ascii_uppercase();
+                if sender.send(&buffer[..len]).is_err() {
+                    break false;
+                }
+            };
+            drop(sender);
+            drop(receiver);
+            exit_child(ok);
+        }
+
+        let mut sender = requests.into_sender();
+        let mut receiver = responses.into_receiver();
+        for word in ["hello", "from", "the", "parent"] {
+            sender.send(word.as_bytes()).unwrap();
+            assert_eq!(receiver.receive(), Some(word.to_uppercase().into_bytes()));
+        }
+        drop(sender);
+        assert_eq!(receiver.receive(), None);
+        assert_eq!(wait_for_child(pid), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn message_bigger_than_the_ring_panics() {
+        let (mut sender, _receiver) = pair(16);
+        let _ = sender.send(&[0; 13]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn receiving_the_wrong_type_panics() {
+        let (mut sender, mut receiver) = pair(64);
+        sender.send_value(1u8).unwrap();
+        receiver.receive_value::<u64>();
+    }
+}
@@ -1,6 +1,26 @@
+pub mod broadcast;
 pub mod cell;
+pub mod channel_io;
 pub mod channels;
+pub mod coalescing_channel;
+pub mod executor;
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+pub mod ipc_channel;
 pub mod iterators;
+pub mod lock_free_channel;
+pub mod oneshot;
+mod poison;
+pub mod priority_channel;
 pub mod rc;
+mod receiver;
 pub mod ref_cell;
+pub mod rpc_channel;
+pub mod spsc;
 pub mod str_split;
+#[cfg(test)]
+mod test_util;
+pub mod timer;
+pub mod watch;
@@ -0,0 +1,703 @@
+// Same Sender/Receiver API as channels, but the Mutex<VecDeque<T>> is swapped for a lock-free linked list queue. With the Mutex every send
+// has to wait for every other send (and receive) to let go of the lock, here senders never wait on each other at all.
+//
+// The queue is Dmitry Vyukov's intrusive MPSC queue. Producers push by swapping themselves in as the newest node and only then linking the
+// previous newest node to themselves. The single consumer walks the list from the oldest end. There is always one node in the list with no
+// value in it, the stub, so the consumer never has to touch the end producers are fighting over.
+//
+// It's multi producer, single consumer only, so the Receiver is not Clone. The receiver parks when the queue is empty, like the consumer in
+// parking_and_condition_variables::parking over in rust-concurrency, and the sender that finds it parked wakes it up.
+//
+// What channels has that this doesn't:
+// - No sync_channel, try_send or capacity. A bounded queue would need senders to wait for space, and waiting on each other is exactly
+//   what this one is built to avoid. It's always unbounded.
+// - No Select. Select registers with a channel's Mutex and every send pokes whoever registered, that's a lock on every send again.
+// - No receive_async, for the same reason, the waker would have to live behind a lock the senders take.
+// - No receiver_count beyond 0 or 1 and no clone, there's only ever the one receiver.
+
+use std::{
+    cell::UnsafeCell,
+    collections::{VecDeque, vec_deque},
+    ptr,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering, fence},
+    },
+    thread::{self, Thread},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    channels::{ReceiveTimeoutError, SendError, TryReceiveError},
+    poison::lock,
+    receiver,
+};
+
+struct Node<T> {
+    next: AtomicPtr<Node<T>>,
+    // None only for the stub.
+    value: Option<T>,
+}
+
+impl<T> Node<T> {
+    fn new(value: Option<T>) -> *mut Node<T> {
+        Box::into_raw(Box::new(Node {
+            next: AtomicPtr::new(ptr::null_mut()),
+            value,
+        }))
+    }
+}
+
+enum Pop<T> {
+    Data(T),
+    Empty,
+    // A producer has swapped itself in but not linked the previous node yet. There is a value coming, give it a moment.
+    Inconsistent,
+}
+
+struct Queue<T> {
+    // Newest node, producers swap themselves in here.
+    head: AtomicPtr<Node<T>>,
+    // Oldest node, the stub. Only the consumer ever touches it so it doesn't need to be atomic.
+    tail: UnsafeCell<*mut Node<T>>,
+}
+
+// The nodes are only ever handed from producers to the consumer through the atomics, and only one consumer ever reads tail.
+unsafe impl<T: Send> Send for Queue<T> {}
+unsafe impl<T: Send> Sync for Queue<T> {}
+
+impl<T> Queue<T> {
+    fn new() -> Self {
+        let stub = Node::new(None);
+        Queue {
+            head: AtomicPtr::new(stub),
+            tail: UnsafeCell::new(stub),
+        }
+    }
+
+    fn push(&self, value: T) {
+        let node = Node::new(Some(value));
+        // From here on we are the newest node. Release so whoever follows our next pointer sees the value, Acquire for the previous node.
+        let previous = self.head.swap(node, Ordering::AcqRel);
+        // SAFETY: The consumer only frees a node after moving past it, which needs its next to be set, and setting it is our job alone.
+        // So previous is still alive.
+        unsafe { (*previous).next.store(node, Ordering::Release) };
+    }
+
+    // SAFETY: Only one thread at a time may pop, the caller makes sure of that.
+    unsafe fn pop(&self) -> Pop<T> {
+        unsafe {
+            let tail = *self.tail.get();
+            let next = (*tail).next.load(Ordering::Acquire);
+
+            if !next.is_null() {
+                // next becomes the new stub, we take its value out and free the old one.
+                *self.tail.get() = next;
+                let value = (*next).value.take().expect("only the stub has no value");
+                drop(Box::from_raw(tail));
+                return Pop::Data(value);
+            }
+
+            if self.head.load(Ordering::Acquire) == tail {
+                Pop::Empty
+            } else {
+                Pop::Inconsistent
+            }
+        }
+    }
+
+    // Whether a producer has pushed anything that hasn't been popped, linked up or not.
+    fn is_empty(&self) -> bool {
+        // SAFETY: Only the consumer calls this, same as pop.
+        self.head.load(Ordering::SeqCst) == unsafe { *self.tail.get() }
+    }
+}
+
+impl<T> Drop for Queue<T> {
+    fn drop(&mut self) {
+        // Nobody else is around anymore, free everything from the stub onwards, values included.
+        let mut node = *self.tail.get_mut();
+        while !node.is_null() {
+            // SAFETY: Every node was made by Box::into_raw and is freed exactly once, here or in pop.
+            let boxed = unsafe { Box::from_raw(node) };
+            node = boxed.next.load(Ordering::Relaxed);
+        }
+    }
+}
+
+struct Shared<T> {
+    queue: Queue<T>,
+    senders: AtomicUsize,
+    receiver_alive: AtomicBool,
+    // Set by the receiver right before it parks. Whoever swaps it back to false gets to wake it up.
+    receiver_waiting: AtomicBool,
+    // Only locked when the receiver is about to park and by the one sender that wakes it, never on the fast path.
+    receiver_thread: Mutex<Option<Thread>>,
+    // Who gets to pop once the receiver is gone and whether somebody pushed since they last did, see drop_unreceived.
+    draining: AtomicBool,
+    pushed_since_drain: AtomicBool,
+    // Same metrics as channels, Relaxed for the same reason. Nothing is synchronized through them.
+    total_sent: AtomicUsize,
+    total_received: AtomicUsize,
+    high_water_mark: AtomicUsize,
+    sender_panicked: AtomicBool,
+}
+
+impl<T> Shared<T> {
+    fn wake_receiver(&self) {
+        // Pairs with the fence in Receiver::park. Either the receiver sees what we just did when it checks one last time before parking,
+        // or we see that it's waiting. Without it both sides could miss each other and the receiver would sleep forever.
+        fence(Ordering::SeqCst);
+        if self.receiver_waiting.load(Ordering::Relaxed)
+            && self.receiver_waiting.swap(false, Ordering::AcqRel)
+            && let Some(thread) = lock(&self.receiver_thread).as_ref()
+        {
+            thread.unpark();
+        }
+    }
+
+    // Same as in channels. received first, the other way round a send and its receive could both happen in between.
+    fn len(&self) -> usize {
+        let received = self.total_received.load(Ordering::Relaxed);
+        self.total_sent
+            .load(Ordering::Relaxed)
+            .saturating_sub(received)
+    }
+
+    // A close guess, same as in channels. The receiver can hand values out while we look.
+    fn record_sent(&self) {
+        let sent = self.total_sent.fetch_add(1, Ordering::Relaxed) + 1;
+        let len = sent.saturating_sub(self.total_received.load(Ordering::Relaxed));
+        self.high_water_mark.fetch_max(len, Ordering::Relaxed);
+    }
+
+    // Once the receiver is gone nobody pops anymore, and without this the values would sit in the queue until the last sender lets go
+    // of the channel. So whoever finds out the receiver is gone drops them right away, that's the receiver's own drop or a sender whose
+    // push raced with it. Popping is still one thread at a time, draining is the lock for that. A thread that doesn't get it leaves
+    // pushed_since_drain set and returns, the one holding it checks that after letting go and comes back for whatever it missed.
+    fn drop_unreceived(&self) {
+        self.pushed_since_drain.store(true, Ordering::SeqCst);
+        while self.pushed_since_drain.load(Ordering::SeqCst) {
+            if self.draining.swap(true, Ordering::SeqCst) {
+                return;
+            }
+            self.pushed_since_drain.store(false, Ordering::SeqCst);
+
+            let mut dropped = 0;
+            loop {
+                // SAFETY: The receiver is gone so it doesn't pop anymore, and draining keeps everybody else out.
+                match unsafe { self.queue.pop() } {
+                    Pop::Data(_) => dropped += 1,
+                    Pop::Inconsistent => std::hint::spin_loop(),
+                    Pop::Empty => break,
+                }
+            }
+            // Like channels, values dropped unread count as received.
+            self.total_received.fetch_add(dropped, Ordering::Relaxed);
+
+            self.draining.store(false, Ordering::SeqCst);
+        }
+    }
+}
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+// Same as channels, no #[derive(Clone)] cause Arc is Clone no matter what T is.
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::Relaxed);
+        Sender {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        // Same as in channels, a sender going away during a panic most likely didn't get to send everything it meant to.
+        if thread::panicking() {
+            self.shared.sender_panicked.store(true, Ordering::Relaxed);
+        }
+
+        // The receiver has to find out that nothing else is coming.
+        if self.shared.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.shared.wake_receiver();
+        }
+    }
+}
+
+impl<T> Sender<T> {
+    pub fn send(&mut self, data_to_send: T) -> Result<(), SendError<T>> {
+        if !self.shared.receiver_alive.load(Ordering::Acquire) {
+            return Err(SendError(data_to_send));
+        }
+
+        self.shared.queue.push(data_to_send);
+        self.shared.record_sent();
+
+        // The receiver can go away between the check above and the push. Pairs with the fence in Receiver::drop, either its drop_unreceived
+        // sees our value or we see that it's gone and drop the value ourselves. Same as in channels, a value that made it in before the
+        // receiver left counts as sent.
+        fence(Ordering::SeqCst);
+        if self.shared.receiver_alive.load(Ordering::Acquire) {
+            self.shared.wake_receiver();
+        } else {
+            self.shared.drop_unreceived();
+        }
+        Ok(())
+    }
+
+    // How many values were sent and not received yet. Already old by the time you look at it with other threads around.
+    pub fn len(&self) -> usize {
+        self.shared.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn sender_count(&self) -> usize {
+        self.shared.senders.load(Ordering::Relaxed)
+    }
+
+    pub fn receiver_count(&self) -> usize {
+        usize::from(self.shared.receiver_alive.load(Ordering::Relaxed))
+    }
+
+    // The receiver is gone, every send from here on fails.
+    pub fn is_disconnected(&self) -> bool {
+        self.receiver_count() == 0
+    }
+
+    pub fn total_sent(&self) -> usize {
+        self.shared.total_sent.load(Ordering::Relaxed)
+    }
+
+    // Values the receiver dropped unread count as well.
+    pub fn total_received(&self) -> usize {
+        self.shared.total_received.load(Ordering::Relaxed)
+    }
+
+    pub fn high_water_mark(&self) -> usize {
+        self.shared.high_water_mark.load(Ordering::Relaxed)
+    }
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    // Only there so receive_batch and drain can hand out a vec_deque::Drain like channels does. Always empty between calls.
+    batch: VecDeque<T>,
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        // Release so whoever sees this also sees all of our pops, they're about to take over popping in drop_unreceived.
+        self.shared.receiver_alive.store(false, Ordering::Release);
+        // Pairs with the fence in send, see there.
+        fence(Ordering::SeqCst);
+        self.shared.drop_unreceived();
+    }
+}
+
+impl<T> Receiver<T> {
+    pub fn receive(&mut self) -> Option<T> {
+        loop {
+            match self.try_receive() {
+                Ok(data) => return Some(data),
+                Err(TryReceiveError::Disconnected) => return None,
+                Err(TryReceiveError::Empty) => {
+                    self.park(None);
+                }
+            }
+        }
+    }
+
+    pub fn try_receive(&mut self) -> Result<T, TryReceiveError> {
+        loop {
+            // SAFETY: We are the only receiver and we have &mut self.
+            match unsafe { self.shared.queue.pop() } {
+                Pop::Data(data) => {
+                    self.shared.total_received.fetch_add(1, Ordering::Relaxed);
+                    return Ok(data);
+                }
+                // It's a matter of a few instructions on the producer's side, spinning is the right call.
+                Pop::Inconsistent => std::hint::spin_loop(),
+                Pop::Empty => {
+                    if self.shared.senders.load(Ordering::Acquire) != 0 {
+                        return Err(TryReceiveError::Empty);
+                    }
+                    // The last sender could have pushed right before going away, look one more time now that we know nobody else will.
+                    if self.shared.queue.is_empty() {
+                        return Err(TryReceiveError::Disconnected);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn receive_timeout(&mut self, timeout: Duration) -> Result<T, ReceiveTimeoutError> {
+        receiver::receive_timeout(self, timeout, Self::receive, Self::receive_deadline)
+    }
+
+    pub fn receive_deadline(&mut self, deadline: Instant) -> Result<T, ReceiveTimeoutError> {
+        loop {
+            match self.try_receive() {
+                Ok(data) => return Ok(data),
+                Err(TryReceiveError::Disconnected) => {
+                    return Err(ReceiveTimeoutError::Disconnected);
+                }
+                Err(TryReceiveError::Empty) => {
+                    if !self.park(Some(deadline)) {
+                        return Err(ReceiveTimeoutError::Timeout);
+                    }
+                }
+            }
+        }
+    }
+
+    // Waits like receive until there is at least one value, then hands out up to max of them. Unlike channels there's no lock to take
+    // them all under at once, it's one pop each, the batch is just whatever was already there once the first one showed up.
+    // An empty batch means the senders are gone and there is nothing left, or max was 0, that one never waits.
+    pub fn receive_batch(&mut self, max: usize) -> vec_deque::Drain<'_, T> {
+        if max > 0
+            && let Some(data) = self.receive()
+        {
+            self.batch.push_back(data);
+            while self.batch.len() < max
+                && let Ok(data) = self.try_receive()
+            {
+                self.batch.push_back(data);
+            }
+        }
+        self.batch.drain(..)
+    }
+
+    // Everything that is available right now, never waits. Only what was there when we started, senders that keep on sending can't keep
+    // us in here forever.
+    pub fn drain(&mut self) -> vec_deque::Drain<'_, T> {
+        for _ in 0..self.len() {
+            match self.try_receive() {
+                Ok(data) => self.batch.push_back(data),
+                Err(_) => break,
+            }
+        }
+        self.batch.drain(..)
+    }
+
+    // Parks until a sender wakes us up or the deadline passes, false means we timed out. Returning doesn't mean there's a value,
+    // the caller loops and checks the queue again either way.
+    fn park(&mut self, deadline: Option<Instant>) -> bool {
+        *lock(&self.shared.receiver_thread) = Some(thread::current());
+        self.shared.receiver_waiting.store(true, Ordering::SeqCst);
+        // Pairs with the fence in wake_receiver, see there.
+        fence(Ordering::SeqCst);
+
+        // Something showed up between our last look and announcing that we're waiting, the sender might not have seen us.
+        if !self.shared.queue.is_empty() || self.shared.senders.load(Ordering::SeqCst) == 0 {
+            self.shared.receiver_waiting.store(false, Ordering::Relaxed);
+            return true;
+        }
+
+        // park can return spuriously, the flag going back to false is what tells us a sender actually woke us.
+        while self.shared.receiver_waiting.load(Ordering::Acquire) {
+            match deadline {
+                None => thread::park(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        // If a sender beat us to it, it's going to unpark us anyways. That just makes some later park return early,
+                        // which every park loop has to deal with regardless.
+                        self.shared.receiver_waiting.store(false, Ordering::Relaxed);
+                        return false;
+                    }
+                    thread::park_timeout(deadline - now);
+                }
+            }
+        }
+        true
+    }
+}
+
+// Same metrics as on the Sender.
+impl<T> Receiver<T> {
+    pub fn len(&self) -> usize {
+        self.shared.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn sender_count(&self) -> usize {
+        self.shared.senders.load(Ordering::Relaxed)
+    }
+
+    // Always 1, it's us.
+    pub fn receiver_count(&self) -> usize {
+        1
+    }
+
+    // All of the senders are gone. There can still be values left to receive, nothing new is coming though.
+    pub fn is_disconnected(&self) -> bool {
+        self.sender_count() == 0
+    }
+
+    pub fn total_sent(&self) -> usize {
+        self.shared.total_sent.load(Ordering::Relaxed)
+    }
+
+    pub fn total_received(&self) -> usize {
+        self.shared.total_received.load(Ordering::Relaxed)
+    }
+
+    pub fn high_water_mark(&self) -> usize {
+        self.shared.high_water_mark.load(Ordering::Relaxed)
+    }
+
+    // Same as in channels, whether any sender was dropped while its thread was panicking. Once it's set it stays set.
+    pub fn sender_panicked(&self) -> bool {
+        self.shared.sender_panicked.load(Ordering::Relaxed)
+    }
+}
+
+receiver::iterators!(Receiver);
+
+// Its convention to return the Sender first and then the Receiver.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        queue: Queue::new(),
+        senders: AtomicUsize::new(1),
+        receiver_alive: AtomicBool::new(true),
+        receiver_waiting: AtomicBool::new(false),
+        receiver_thread: Mutex::new(None),
+        draining: AtomicBool::new(false),
+        pushed_since_drain: AtomicBool::new(false),
+        total_sent: AtomicUsize::new(0),
+        total_received: AtomicUsize::new(0),
+        high_water_mark: AtomicUsize::new(0),
+        sender_panicked: AtomicBool::new(false),
+    });
+
+    (
+        Sender {
+            shared: Arc::clone(&shared),
+        },
+        Receiver {
+            shared,
+            batch: VecDeque::new(),
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::DropCounter;
+
+    #[test]
+    fn ping_pong() {
+        let (mut sender, mut receiver) = channel();
+        sender.send(42).unwrap();
+        assert_eq!(receiver.receive(), Some(42));
+    }
+
+    #[test]
+    fn drop_sender_instantly() {
+        let (sender, mut receiver) = channel::<()>();
+        drop(sender);
+        assert_eq!(receiver.receive(), None);
+    }
+
+    #[test]
+    fn values_sent_before_disconnect_are_delivered() {
+        let (mut sender, mut receiver) = channel();
+        sender.send(1).unwrap();
+        sender.send(2).unwrap();
+        drop(sender);
+
+        assert_eq!(receiver.iter().collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(receiver.try_receive(), Err(TryReceiveError::Disconnected));
+    }
+
+    #[test]
+    fn receive_parks_until_send() {
+        let (mut sender, mut receiver) = channel();
+
+        let t = thread::spawn(move || receiver.receive());
+
+        thread::sleep(Duration::from_millis(50));
+        sender.send(7).unwrap();
+        assert_eq!(t.join().unwrap(), Some(7));
+    }
+
+    #[test]
+    fn drop_sender_while_receiver_parks() {
+        let (sender, mut receiver) = channel::<()>();
+
+        let t = thread::spawn(move || receiver.receive());
+
+        thread::sleep(Duration::from_millis(50));
+        drop(sender);
+        assert_eq!(t.join().unwrap(), None);
+    }
+
+    #[test]
+    fn send_fails_without_receiver() {
+        let (mut sender, receiver) = channel();
+        drop(receiver);
+        assert_eq!(sender.send(1), Err(SendError(1)));
+    }
+
+    #[test]
+    fn receive_timeout() {
+        let (mut sender, mut receiver) = channel();
+        assert_eq!(
+            receiver.receive_timeout(Duration::from_millis(20)),
+            Err(ReceiveTimeoutError::Timeout)
+        );
+
+        sender.send(1).unwrap();
+        assert_eq!(receiver.receive_timeout(Duration::from_millis(20)), Ok(1));
+
+        drop(sender);
+        assert_eq!(
+            receiver.receive_timeout(Duration::from_millis(20)),
+            Err(ReceiveTimeoutError::Disconnected)
+        );
+    }
+
+    #[test]
+    fn unreceived_values_are_dropped() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let (mut sender, receiver) = channel();
+        for _ in 0..3 {
+            sender.send(DropCounter(drops.clone())).unwrap();
+        }
+
+        drop(receiver);
+        drop(sender);
+        assert_eq!(drops.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn values_are_dropped_with_the_receiver() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let (mut sender, receiver) = channel();
+        for _ in 0..3 {
+            sender.send(DropCounter(drops.clone())).unwrap();
+        }
+
+        // The sender is still around, the values don't wait for it anymore.
+        drop(receiver);
+        assert_eq!(drops.load(Ordering::Relaxed), 3);
+        assert!(sender.is_disconnected());
+        assert!(sender.send(DropCounter(drops.clone())).is_err());
+        assert_eq!(drops.load(Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    fn sends_racing_the_receivers_drop_are_not_kept() {
+        const SENDERS: usize = 4;
+        let drops = Arc::new(AtomicUsize::new(0));
+        let (sender, mut receiver) = channel();
+
+        let senders: Vec<_> = (0..SENDERS)
+            .map(|_| {
+                let mut sender = sender.clone();
+                let drops = Arc::clone(&drops);
+                thread::spawn(move || {
+                    let mut sent = 0;
+                    while sender.send(DropCounter(Arc::clone(&drops))).is_ok() {
+                        sent += 1;
+                    }
+                    // Handed back so the channel stays alive until we've counted.
+                    (sender, sent)
+                })
+            })
+            .collect();
+
+        for _ in 0..100 {
+            receiver.receive().unwrap();
+        }
+        drop(receiver);
+
+        let results: Vec<_> = senders.into_iter().map(|t| t.join().unwrap()).collect();
+        let sent: usize = results.iter().map(|(_, sent)| sent).sum();
+        // Every failed send dropped its value too, one per sender.
+        assert_eq!(drops.load(Ordering::Relaxed), sent + SENDERS);
+        assert_eq!(sender.len(), 0);
+    }
+
+    #[test]
+    fn receive_batch_and_drain() {
+        let (mut sender, mut receiver) = channel();
+        for i in 0..5 {
+            sender.send(i).unwrap();
+        }
+
+        assert_eq!(receiver.receive_batch(0).count(), 0);
+        assert_eq!(receiver.receive_batch(2).collect::<Vec<_>>(), vec![0, 1]);
+        assert_eq!(receiver.drain().collect::<Vec<_>>(), vec![2, 3, 4]);
+        assert_eq!(receiver.drain().count(), 0);
+
+        drop(sender);
+        assert_eq!(receiver.receive_batch(4).count(), 0);
+    }
+
+    #[test]
+    fn metrics() {
+        let (mut sender, mut receiver) = channel();
+        let other = sender.clone();
+        for i in 0..3 {
+            sender.send(i).unwrap();
+        }
+        receiver.receive().unwrap();
+
+        assert_eq!(sender.len(), 2);
+        assert_eq!(receiver.len(), 2);
+        assert_eq!(receiver.sender_count(), 2);
+        assert_eq!(sender.receiver_count(), 1);
+        assert_eq!(receiver.total_sent(), 3);
+        assert_eq!(sender.total_received(), 1);
+        assert_eq!(receiver.high_water_mark(), 3);
+
+        drop(other);
+        drop(sender);
+        assert!(receiver.is_disconnected());
+        assert!(!receiver.sender_panicked());
+    }
+
+    #[test]
+    fn many_producers_keep_their_own_order() {
+        const PRODUCERS: usize = 8;
+        const PER_PRODUCER: usize = 10_000;
+
+        let (sender, receiver) = channel();
+
+        let producers: Vec<_> = (0..PRODUCERS)
+            .map(|producer| {
+                let mut sender = sender.clone();
+                thread::spawn(move || {
+                    for sequence in 0..PER_PRODUCER {
+                        sender.send((producer, sequence)).unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(sender);
+
+        // Messages from different producers interleave however they like, but each producer's own messages have to show up in the order it sent them.
+        let mut next_expected = [0; PRODUCERS];
+        for (producer, sequence) in receiver {
+            assert_eq!(sequence, next_expected[producer]);
+            next_expected[producer] += 1;
+        }
+        assert_eq!(next_expected, [PER_PRODUCER; PRODUCERS]);
+
+        for producer in producers {
+            producer.join().unwrap();
+        }
+    }
+}
@@ -0,0 +1,274 @@
+// A channel for exactly one value. The classic use is getting a result back from a spawned thread.
+// The regular channel works for that too but it drags a Mutex, a VecDeque and two Condvars along for a single value.
+// Here the whole state is one atomic, the value lives in an UnsafeCell and the receiver parks itself like the consumer in
+// parking_and_condition_variables::parking over in rust-concurrency.
+
+use std::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    sync::{
+        Arc,
+        atomic::{AtomicU8, Ordering},
+    },
+    thread::{self, Thread},
+};
+
+use crate::channels::SendError;
+
+// Nothing sent yet and the receiver isn't waiting.
+const EMPTY: u8 = 0;
+// Nothing sent yet and the receiver is parked, its thread handle is in receiving_thread.
+const WAITING: u8 = 1;
+// The value has been written and is up for grabs.
+const READY: u8 = 2;
+// Done. Either side went away, or the value was already taken out. Nothing in the slot either way.
+const CLOSED: u8 = 3;
+
+struct Shared<T> {
+    state: AtomicU8,
+    // MaybeUninit cause there is no value until the sender writes one. The state tells us whether it's safe to read.
+    value: UnsafeCell<MaybeUninit<T>>,
+    receiving_thread: UnsafeCell<Option<Thread>>,
+}
+
+// The state makes sure only one side touches value and receiving_thread at a time. The value moves across threads, hence T: Send.
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+// Its convention to return the Sender first and then the Receiver. Neither of them is Clone, it's one value from one place to one place.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        state: AtomicU8::new(EMPTY),
+        value: UnsafeCell::new(MaybeUninit::uninit()),
+        receiving_thread: UnsafeCell::new(None),
+    });
+
+    (
+        Sender {
+            shared: Arc::clone(&shared),
+        },
+        Receiver { shared },
+    )
+}
+
+impl<T> Sender<T> {
+    // Takes self so you can't send twice, the compiler does that check for us.
+    pub fn send(self, data_to_send: T) -> Result<(), SendError<T>> {
+        // SAFETY: Only the sender ever writes the value and this is the only time it can do so. The receiver doesn't read it before it sees READY.
+        unsafe { (*self.shared.value.get()).write(data_to_send) };
+
+        // Release so the receiver sees the value once it sees READY, Acquire so we see the thread handle if it was WAITING.
+        match self.shared.state.swap(READY, Ordering::AcqRel) {
+            EMPTY => Ok(()),
+            WAITING => {
+                // SAFETY: The receiver wrote its thread before it published WAITING and never touches it again.
+                if let Some(thread) = unsafe { &*self.shared.receiving_thread.get() } {
+                    thread.unpark();
+                }
+                Ok(())
+            }
+            // The receiver is gone and nobody is going to read it, take it right back out and give it to the caller.
+            CLOSED => {
+                self.shared.state.store(CLOSED, Ordering::Relaxed);
+                // SAFETY: We wrote it just above and the receiver never saw it as READY, so it's still there and still ours.
+                let data = unsafe { (*self.shared.value.get()).assume_init_read() };
+                Err(SendError(data))
+            }
+            _ => unreachable!("a oneshot value can only be sent once"),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        // Only close it if nothing was sent. After a send the state is READY (or CLOSED if the receiver already took it) and has to stay that way.
+        let previous =
+            self.shared
+                .state
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |state| {
+                    matches!(state, EMPTY | WAITING).then_some(CLOSED)
+                });
+
+        // The receiver is parked waiting for a value that is never coming, wake it up so it can find out.
+        if previous == Ok(WAITING) {
+            // SAFETY: Same as in send, WAITING means the thread handle has been written and won't change.
+            if let Some(thread) = unsafe { &*self.shared.receiving_thread.get() } {
+                thread.unpark();
+            }
+        }
+    }
+}
+
+impl<T> Receiver<T> {
+    // Blocks until the value shows up. None means the sender was dropped without sending anything.
+    pub fn receive(self) -> Option<T> {
+        if self.shared.state.load(Ordering::Acquire) == EMPTY {
+            // SAFETY: The sender only reads this after it sees WAITING, which we haven't published yet.
+            unsafe { *self.shared.receiving_thread.get() = Some(thread::current()) };
+            // If this fails the sender beat us to it, the loop below picks up whatever it left.
+            let _ = self.shared.state.compare_exchange(
+                EMPTY,
+                WAITING,
+                Ordering::Release,
+                Ordering::Relaxed,
+            );
+        }
+
+        // park can return spuriously so we loop on the state, same as with a condvar.
+        loop {
+            match self.shared.state.load(Ordering::Acquire) {
+                READY => {
+                    // Mark it as taken so our own drop below doesn't drop the value a second time.
+                    self.shared.state.store(CLOSED, Ordering::Relaxed);
+                    // SAFETY: READY means the sender is done writing it, and the Acquire load makes sure we see the write.
+                    return Some(unsafe { (*self.shared.value.get()).assume_init_read() });
+                }
+                CLOSED => return None,
+                _ => thread::park(),
+            }
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        // From here on a send hands the value back instead. If it already happened though, the value is sitting in the slot and it's on us to drop it,
+        // MaybeUninit never drops what's inside on its own.
+        if self.shared.state.swap(CLOSED, Ordering::Acquire) == READY {
+            // SAFETY: READY means there is an initialized value that nobody took out, and CLOSED makes sure nobody else will.
+            unsafe { (*self.shared.value.get()).assume_init_drop() };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::DropCounter;
+    use std::sync::atomic::AtomicUsize;
+    use std::time::Duration;
+
+    #[test]
+    fn send_then_receive() {
+        let (sender, receiver) = channel();
+        sender.send(42).unwrap();
+        assert_eq!(receiver.receive(), Some(42));
+    }
+
+    #[test]
+    fn receive_waits_for_spawned_thread() {
+        let (sender, receiver) = channel();
+
+        let t = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            sender.send(String::from("done")).unwrap();
+        });
+
+        assert_eq!(receiver.receive().as_deref(), Some("done"));
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn receive_on_another_thread() {
+        let (sender, receiver) = channel();
+
+        let t = thread::spawn(move || receiver.receive());
+
+        thread::sleep(Duration::from_millis(50));
+        sender.send(1).unwrap();
+        assert_eq!(t.join().unwrap(), Some(1));
+    }
+
+    #[test]
+    fn drop_sender_first() {
+        let (sender, receiver) = channel::<()>();
+        drop(sender);
+        assert_eq!(receiver.receive(), None);
+    }
+
+    #[test]
+    fn drop_sender_while_receiver_waits() {
+        let (sender, receiver) = channel::<()>();
+
+        let t = thread::spawn(move || receiver.receive());
+
+        thread::sleep(Duration::from_millis(50));
+        drop(sender);
+        assert_eq!(t.join().unwrap(), None);
+    }
+
+    #[test]
+    fn drop_receiver_first() {
+        let (sender, receiver) = channel();
+        drop(receiver);
+        assert_eq!(
+            sender.send(String::from("lost")).unwrap_err().into_inner(),
+            "lost"
+        );
+    }
+
+    #[test]
+    fn sent_but_never_received_is_dropped() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let (sender, receiver) = channel();
+
+        sender.send(DropCounter(drops.clone())).unwrap();
+        assert_eq!(drops.load(Ordering::Relaxed), 0);
+
+        drop(receiver);
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn received_value_is_dropped_once() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let (sender, receiver) = channel();
+
+        sender.send(DropCounter(drops.clone())).unwrap();
+        let data = receiver.receive().unwrap();
+        assert_eq!(drops.load(Ordering::Relaxed), 0);
+
+        drop(data);
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn send_to_dropped_receiver_is_not_dropped_twice() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let (sender, receiver) = channel();
+        drop(receiver);
+
+        let error = sender.send(DropCounter(drops.clone())).unwrap_err();
+        assert_eq!(drops.load(Ordering::Relaxed), 0);
+
+        drop(error);
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn racing_send_and_receiver_drop() {
+        // Whichever side wins, the value has to be dropped exactly once.
+        for _ in 0..100 {
+            let drops = Arc::new(AtomicUsize::new(0));
+            let (sender, receiver) = channel();
+
+            let t = thread::spawn({
+                let drops = drops.clone();
+                move || {
+                    let _ = sender.send(DropCounter(drops));
+                }
+            });
+            drop(receiver);
+            t.join().unwrap();
+
+            assert_eq!(drops.load(Ordering::Relaxed), 1);
+        }
+    }
+}
@@ -0,0 +1,33 @@
+// Locking that doesn't care about poisoning. Shared by every channel that keeps its state behind a Mutex.
+//
+// The Mutex is poisoned when a thread panics while holding it, and from then on lock().unwrap() would panic every other thread that uses the
+// channel. Worse, it panics in Drop impls too, and a panic in a destructor while the thread is already unwinding aborts the whole process.
+// Poisoning exists to flag data that could be left half updated. The channels make sure that can't happen, whatever user code could panic
+// (a Drop, a Clone, a Hash, whatever holds on to a watch Ref) either runs outside the lock or runs while nothing is halfway through an update.
+// So the guard is as good as new, we take it out of the PoisonError and carry on.
+
+use std::{
+    sync::{Condvar, Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+pub(crate) fn lock<U>(mutex: &Mutex<U>) -> MutexGuard<'_, U> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// Same thing for the condvar waits, they lock the Mutex again before returning and hand back the same PoisonError if it's been poisoned.
+pub(crate) fn wait<'a, U>(condvar: &Condvar, guard: MutexGuard<'a, U>) -> MutexGuard<'a, U> {
+    condvar.wait(guard).unwrap_or_else(PoisonError::into_inner)
+}
+
+// Drops the timed out flag, everybody calling this checks the deadline themselves anyways.
+pub(crate) fn wait_timeout<'a, U>(
+    condvar: &Condvar,
+    guard: MutexGuard<'a, U>,
+    timeout: Duration,
+) -> MutexGuard<'a, U> {
+    condvar
+        .wait_timeout(guard, timeout)
+        .unwrap_or_else(PoisonError::into_inner)
+        .0
+}
@@ -0,0 +1,478 @@
+// Same thing as channels, except the receiver doesn't get the oldest message, it gets the most urgent one. Every message is sent with a priority,
+// higher goes first, and messages with the same priority come out in the order they were sent.
+//
+// The Mutex + two Condvars setup is exactly the one from channels, only the VecDeque is swapped for a BinaryHeap. A heap on its own isn't
+// FIFO for equal priorities though, so every message also gets a sequence number and the earlier one wins a tie.
+// Poisoning is dealt with the same way too, see poison. The heap only ever compares priorities and sequence numbers, never a T,
+// and T's drop runs after we let go, so nothing under the lock can panic halfway through an update.
+//
+// There's no receive side buffer like in channels. Grabbing a batch would mean a more urgent message sent right after has to wait behind it.
+//
+// Bounded works the same as channels::sync_channel with one difference, there's no rendezvous. A rendezvous channel never has more than
+// the one message in it, so there's nothing to pick the most urgent one from. sync_priority_channel takes a NonZeroUsize so a capacity
+// of 0 can't even be asked for.
+
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    num::NonZeroUsize,
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    channels::{ReceiveTimeoutError, SendError, TryReceiveError},
+    poison::{lock, wait, wait_timeout},
+    receiver,
+};
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+// Same as channels, no #[derive(Clone)] cause Arc is Clone no matter what T is.
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        let mut inner = lock(&self.shared.inner);
+        inner.senders += 1;
+        drop(inner);
+
+        Sender {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut inner = lock(&self.shared.inner);
+        inner.senders -= 1;
+        let is_last_sender = inner.senders == 0;
+        drop(inner);
+
+        // Every waiting receiver has to find out that nothing else is coming.
+        if is_last_sender {
+            self.shared.signal_data_sent.notify_all();
+        }
+    }
+}
+
+impl<T> Sender<T> {
+    // Same as send_with_priority with the lowest priority there is, so anything sent with a priority goes ahead of it.
+    pub fn send(&mut self, data_to_send: T) -> Result<(), SendError<T>> {
+        self.send_with_priority(data_to_send, 0)
+    }
+
+    // Higher priority is received first. Blocks on a full bounded channel the same way channels::Sender::send does,
+    // and fails the same way once all of the receivers are gone.
+    pub fn send_with_priority(
+        &mut self,
+        data_to_send: T,
+        priority: u32,
+    ) -> Result<(), SendError<T>> {
+        let mut inner = lock(&self.shared.inner);
+
+        if inner.receivers == 0 {
+            return Err(SendError(data_to_send));
+        }
+
+        if let Some(capacity) = self.shared.capacity {
+            while inner.heap.len() >= capacity {
+                inner = wait(&self.shared.signal_space_available, inner);
+                if inner.receivers == 0 {
+                    return Err(SendError(data_to_send));
+                }
+            }
+        }
+
+        let sequence = inner.next_sequence;
+        inner.next_sequence += 1;
+        inner.heap.push(Entry {
+            priority,
+            sequence,
+            data: data_to_send,
+        });
+        drop(inner);
+
+        self.shared.signal_data_sent.notify_one();
+        Ok(())
+    }
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        let mut inner = lock(&self.shared.inner);
+        inner.receivers += 1;
+        drop(inner);
+
+        Receiver {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut inner = lock(&self.shared.inner);
+        inner.receivers -= 1;
+        let is_last_receiver = inner.receivers == 0;
+
+        // Nobody is going to read what's left, drop it outside the lock same as channels does.
+        let unread = if is_last_receiver {
+            std::mem::take(&mut inner.heap)
+        } else {
+            BinaryHeap::new()
+        };
+        drop(inner);
+        drop(unread);
+
+        // Senders blocked on a full channel would wait forever otherwise.
+        if is_last_receiver {
+            self.shared.signal_space_available.notify_all();
+        }
+    }
+}
+
+impl<T> Receiver<T> {
+    // Blocks until there's a message and hands out the most urgent one. None once all of the senders are gone and everything has been read.
+    pub fn receive(&mut self) -> Option<T> {
+        let mut inner = lock(&self.shared.inner);
+
+        loop {
+            if let Some(entry) = inner.heap.pop() {
+                drop(inner);
+                self.shared.signal_space_available.notify_one();
+                return Some(entry.data);
+            }
+
+            if inner.senders == 0 {
+                return None;
+            }
+
+            inner = wait(&self.shared.signal_data_sent, inner);
+        }
+    }
+
+    pub fn try_receive(&mut self) -> Result<T, TryReceiveError> {
+        let mut inner = lock(&self.shared.inner);
+
+        if let Some(entry) = inner.heap.pop() {
+            drop(inner);
+            self.shared.signal_space_available.notify_one();
+            return Ok(entry.data);
+        }
+
+        if inner.senders == 0 {
+            Err(TryReceiveError::Disconnected)
+        } else {
+            Err(TryReceiveError::Empty)
+        }
+    }
+
+    pub fn receive_timeout(&mut self, timeout: Duration) -> Result<T, ReceiveTimeoutError> {
+        receiver::receive_timeout(self, timeout, Self::receive, Self::receive_deadline)
+    }
+
+    pub fn receive_deadline(&mut self, deadline: Instant) -> Result<T, ReceiveTimeoutError> {
+        let mut inner = lock(&self.shared.inner);
+
+        // Same loop as in channels, the heap is checked before the clock so a message that came in right as we timed out still gets picked up.
+        loop {
+            if let Some(entry) = inner.heap.pop() {
+                drop(inner);
+                self.shared.signal_space_available.notify_one();
+                return Ok(entry.data);
+            }
+
+            if inner.senders == 0 {
+                return Err(ReceiveTimeoutError::Disconnected);
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(ReceiveTimeoutError::Timeout);
+            }
+
+            inner = wait_timeout(&self.shared.signal_data_sent, inner, deadline - now);
+        }
+    }
+}
+
+// What actually sits in the heap. Only priority and sequence take part in the ordering, so T doesn't have to be Ord.
+struct Entry<T> {
+    priority: u32,
+    // Counts up with every send. BinaryHeap is a max heap, so the comparison is flipped for it, the smaller (older) one has to come out first.
+    sequence: u64,
+    data: T,
+}
+
+impl<T> PartialEq for Entry<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T> Eq for Entry<T> {}
+
+impl<T> PartialOrd for Entry<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Entry<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.sequence.cmp(&self.sequence))
+    }
+}
+
+struct Inner<T> {
+    heap: BinaryHeap<Entry<T>>,
+    next_sequence: u64,
+    senders: usize,
+    receivers: usize,
+}
+
+struct Shared<T> {
+    inner: Mutex<Inner<T>>,
+    signal_data_sent: Condvar,
+    signal_space_available: Condvar,
+    // None means unbounded. Never changes so it's outside the Mutex.
+    capacity: Option<usize>,
+}
+
+// Its convention to return the Sender first and then the Receiver.
+pub fn priority_channel<T>() -> (Sender<T>, Receiver<T>) {
+    new_channel(None)
+}
+
+// Same as channels::sync_channel, send blocks once capacity messages are waiting. No rendezvous version, see the top of the file.
+pub fn sync_priority_channel<T>(capacity: NonZeroUsize) -> (Sender<T>, Receiver<T>) {
+    new_channel(Some(capacity.get()))
+}
+
+fn new_channel<T>(capacity: Option<usize>) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        inner: Mutex::new(Inner {
+            heap: BinaryHeap::new(),
+            next_sequence: 0,
+            senders: 1,
+            receivers: 1,
+        }),
+        signal_data_sent: Condvar::new(),
+        signal_space_available: Condvar::new(),
+        capacity,
+    });
+
+    (
+        Sender {
+            shared: Arc::clone(&shared),
+        },
+        Receiver { shared },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn higher_priority_first() {
+        let (mut sender, mut receiver) = priority_channel();
+        sender.send_with_priority("low", 1).unwrap();
+        sender.send_with_priority("high", 10).unwrap();
+        sender.send_with_priority("middle", 5).unwrap();
+
+        assert_eq!(receiver.receive(), Some("high"));
+        assert_eq!(receiver.receive(), Some("middle"));
+        assert_eq!(receiver.receive(), Some("low"));
+    }
+
+    #[test]
+    fn same_priority_is_fifo() {
+        let (mut sender, mut receiver) = priority_channel();
+        for i in 0..10 {
+            sender.send_with_priority(i, (i % 2) as u32).unwrap();
+        }
+
+        let got: Vec<_> = (0..10).map(|_| receiver.receive().unwrap()).collect();
+        assert_eq!(got, vec![1, 3, 5, 7, 9, 0, 2, 4, 6, 8]);
+    }
+
+    #[test]
+    fn plain_send_goes_last() {
+        let (mut sender, mut receiver) = priority_channel();
+        sender.send("plain").unwrap();
+        sender.send_with_priority("urgent", 1).unwrap();
+
+        assert_eq!(receiver.receive(), Some("urgent"));
+        assert_eq!(receiver.receive(), Some("plain"));
+    }
+
+    #[test]
+    fn receive_waits_for_send() {
+        let (mut sender, mut receiver) = priority_channel();
+
+        let t = thread::spawn(move || receiver.receive());
+
+        thread::sleep(Duration::from_millis(50));
+        assert!(!t.is_finished());
+        sender.send_with_priority(3, 7).unwrap();
+        assert_eq!(t.join().unwrap(), Some(3));
+    }
+
+    #[test]
+    fn disconnected_after_reading_everything() {
+        let (mut sender, mut receiver) = priority_channel();
+        sender.send(1).unwrap();
+        drop(sender);
+
+        assert_eq!(receiver.try_receive(), Ok(1));
+        assert_eq!(receiver.try_receive(), Err(TryReceiveError::Disconnected));
+        assert_eq!(receiver.receive(), None);
+    }
+
+    #[test]
+    fn dropping_sender_wakes_up_receiver() {
+        let (sender, mut receiver) = priority_channel::<()>();
+
+        let t = thread::spawn(move || receiver.receive());
+
+        thread::sleep(Duration::from_millis(50));
+        drop(sender);
+        assert_eq!(t.join().unwrap(), None);
+    }
+
+    #[test]
+    fn send_fails_without_receivers() {
+        let (mut sender, receiver) = priority_channel();
+        let other = receiver.clone();
+        drop(receiver);
+        assert_eq!(sender.send(1), Ok(()));
+
+        drop(other);
+        assert_eq!(sender.send_with_priority(2, 5), Err(SendError(2)));
+    }
+
+    #[test]
+    fn receive_timeout() {
+        let (mut sender, mut receiver) = priority_channel();
+        assert_eq!(
+            receiver.receive_timeout(Duration::from_millis(20)),
+            Err(ReceiveTimeoutError::Timeout)
+        );
+
+        sender.send(1).unwrap();
+        assert_eq!(receiver.receive_timeout(Duration::from_millis(20)), Ok(1));
+
+        drop(sender);
+        assert_eq!(
+            receiver.receive_timeout(Duration::from_millis(20)),
+            Err(ReceiveTimeoutError::Disconnected)
+        );
+    }
+
+    #[test]
+    fn bounded_send_blocks_until_there_is_room() {
+        let (mut sender, mut receiver) = sync_priority_channel(NonZeroUsize::MIN);
+        sender.send_with_priority(1, 1).unwrap();
+
+        let t = thread::spawn(move || sender.send_with_priority(2, 2));
+
+        thread::sleep(Duration::from_millis(50));
+        assert!(!t.is_finished());
+        assert_eq!(receiver.receive(), Some(1));
+        assert_eq!(t.join().unwrap(), Ok(()));
+        assert_eq!(receiver.receive(), Some(2));
+    }
+
+    #[test]
+    fn dropping_receiver_unblocks_bounded_sender() {
+        let (mut sender, receiver) = sync_priority_channel(NonZeroUsize::MIN);
+        sender.send(1).unwrap();
+
+        let t = thread::spawn(move || sender.send(2));
+
+        thread::sleep(Duration::from_millis(50));
+        drop(receiver);
+        assert_eq!(t.join().unwrap(), Err(SendError(2)));
+    }
+
+    #[test]
+    fn channel_works_with_a_poisoned_lock() {
+        let (mut sender, mut receiver) = sync_priority_channel(NonZeroUsize::MIN);
+
+        // Same as the one in channels, nothing here panics under the lock so we poison it by hand.
+        let shared = Arc::clone(&sender.shared);
+        let result = std::panic::catch_unwind(move || {
+            let _inner = shared.inner.lock().unwrap();
+            panic!("panicked while holding the lock");
+        });
+        assert!(result.is_err());
+        assert!(sender.shared.inner.is_poisoned());
+
+        sender.send_with_priority(1, 1).unwrap();
+        assert_eq!(receiver.try_receive(), Ok(1));
+        assert_eq!(
+            receiver.receive_timeout(Duration::from_millis(10)),
+            Err(ReceiveTimeoutError::Timeout)
+        );
+
+        // Waiting on both condvars works too, the second send has to wait for the receive to make room.
+        sender.send(2).unwrap();
+        let t = thread::spawn(move || sender.send(3));
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(receiver.receive(), Some(2));
+        assert_eq!(t.join().unwrap(), Ok(()));
+        assert_eq!(receiver.receive(), Some(3));
+        assert_eq!(receiver.receive(), None);
+    }
+
+    #[test]
+    fn many_senders_many_receivers() {
+        let (sender, receiver) = priority_channel();
+
+        let senders: Vec<_> = (0..4)
+            .map(|id| {
+                let mut sender = sender.clone();
+                thread::spawn(move || {
+                    for i in 0..100 {
+                        sender.send_with_priority(id * 100 + i, i).unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(sender);
+
+        let receivers: Vec<_> = (0..2)
+            .map(|_| {
+                let mut receiver = receiver.clone();
+                thread::spawn(move || {
+                    let mut got = Vec::new();
+                    while let Some(data) = receiver.receive() {
+                        got.push(data);
+                    }
+                    got
+                })
+            })
+            .collect();
+        drop(receiver);
+
+        for t in senders {
+            t.join().unwrap();
+        }
+        let mut got: Vec<_> = receivers
+            .into_iter()
+            .flat_map(|t| t.join().unwrap())
+            .collect();
+        got.sort();
+        assert_eq!(got, (0..400).collect::<Vec<_>>());
+    }
+}
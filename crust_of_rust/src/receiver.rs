@@ -0,0 +1,103 @@
+// The bits every channel's Receiver would otherwise write out for itself. They're all the same no matter how the channel is built,
+// only receive, try_receive and receive_deadline are specific to each one.
+
+use std::time::{Duration, Instant};
+
+use crate::channels::ReceiveTimeoutError;
+
+// When a timeout that starts now runs out. None if the timeout is so big that the deadline doesn't fit in an Instant,
+// nobody is going to be around to see that one pass, so None means wait forever.
+pub(crate) fn deadline(timeout: Duration) -> Option<Instant> {
+    Instant::now().checked_add(timeout)
+}
+
+// receive_timeout in terms of receive and receive_deadline. The receiver goes in as an argument instead of being captured by both closures,
+// they'd both need the &mut at once otherwise.
+pub(crate) fn receive_timeout<R, T>(
+    receiver: &mut R,
+    timeout: Duration,
+    receive: impl FnOnce(&mut R) -> Option<T>,
+    receive_deadline: impl FnOnce(&mut R, Instant) -> Result<T, ReceiveTimeoutError>,
+) -> Result<T, ReceiveTimeoutError> {
+    match deadline(timeout) {
+        Some(deadline) => receive_deadline(receiver, deadline),
+        None => receive(receiver).ok_or(ReceiveTimeoutError::Disconnected),
+    }
+}
+
+// Iter, TryIter and IntoIter for a Receiver<T> that has receive and try_receive, plus the iter/try_iter methods and the IntoIterator impls.
+// Same idea as the iterators in the iterators module, the state is the receiver and next just asks it for the next item.
+// A macro rather than generic iterators so every channel still has its own channel::Iter and friends to name.
+macro_rules! iterators {
+    ($receiver:ident) => {
+        impl<T> $receiver<T> {
+            // Blocks for every item, ends once all of the senders are gone and the queue is drained. Same thing as looping over receive.
+            pub fn iter(&mut self) -> Iter<'_, T> {
+                Iter { receiver: self }
+            }
+
+            // Never blocks, ends as soon as the queue is empty even if the senders are still around. Handy to drain whatever is there right now.
+            pub fn try_iter(&mut self) -> TryIter<'_, T> {
+                TryIter { receiver: self }
+            }
+        }
+
+        pub struct Iter<'a, T> {
+            receiver: &'a mut $receiver<T>,
+        }
+
+        impl<T> Iterator for Iter<'_, T> {
+            type Item = T;
+
+            fn next(&mut self) -> Option<Self::Item> {
+                self.receiver.receive()
+            }
+        }
+
+        pub struct TryIter<'a, T> {
+            receiver: &'a mut $receiver<T>,
+        }
+
+        impl<T> Iterator for TryIter<'_, T> {
+            type Item = T;
+
+            fn next(&mut self) -> Option<Self::Item> {
+                self.receiver.try_receive().ok()
+            }
+        }
+
+        // Owns the receiver, this is what you get from `for data in receiver`.
+        pub struct IntoIter<T> {
+            receiver: $receiver<T>,
+        }
+
+        impl<T> Iterator for IntoIter<T> {
+            type Item = T;
+
+            fn next(&mut self) -> Option<Self::Item> {
+                self.receiver.receive()
+            }
+        }
+
+        impl<T> IntoIterator for $receiver<T> {
+            type Item = T;
+            type IntoIter = IntoIter<T>;
+
+            fn into_iter(self) -> Self::IntoIter {
+                IntoIter { receiver: self }
+            }
+        }
+
+        // Lets you write `for data in &mut receiver` and still use the receiver afterwards.
+        impl<'a, T> IntoIterator for &'a mut $receiver<T> {
+            type Item = T;
+            type IntoIter = Iter<'a, T>;
+
+            fn into_iter(self) -> Self::IntoIter {
+                self.iter()
+            }
+        }
+    };
+}
+
+pub(crate) use iterators;
@@ -0,0 +1,324 @@
+// Send a request, wait for its answer. You could do it with two channels, one for requests and one for replies, but as soon as there's more
+// than one client they'd have to sort out whose reply is whose. Here every request carries its own oneshot for the answer, so the reply
+// always finds its way back to the one client that asked.
+//
+// The requests go through a regular channels channel, so everything from there carries over. Clients and servers can both be cloned,
+// a cloned Server is a worker pool where every request is picked up by exactly one of them. Once there's more than one receiver a channels
+// receive only takes a single value off the queue, so a busy server never sits on requests an idle one could be handling.
+
+use crate::{
+    channels::{self, SendError},
+    oneshot,
+};
+
+pub struct Client<Req, Resp> {
+    sender: channels::Sender<(Req, oneshot::Sender<Resp>)>,
+}
+
+// Same as channels, no #[derive(Clone)] cause that would want Req: Clone and Resp: Clone.
+impl<Req, Resp> Clone for Client<Req, Resp> {
+    fn clone(&self) -> Self {
+        Client {
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+impl<Req, Resp> Client<Req, Resp> {
+    // Blocks until a server has answered. Fails if there is no server left to take the request, you get it back in that case,
+    // or if the server that took it dropped the Responder without answering.
+    pub fn call(&mut self, request: Req) -> Result<Resp, CallError<Req>> {
+        let (reply_sender, reply_receiver) = oneshot::channel();
+
+        if let Err(SendError((request, _))) = self.sender.send((request, reply_sender)) {
+            return Err(CallError::Disconnected(request));
+        }
+
+        reply_receiver.receive().ok_or(CallError::NoResponse)
+    }
+}
+
+pub struct Server<Req, Resp> {
+    receiver: channels::Receiver<(Req, oneshot::Sender<Resp>)>,
+}
+
+impl<Req, Resp> Clone for Server<Req, Resp> {
+    fn clone(&self) -> Self {
+        Server {
+            receiver: self.receiver.clone(),
+        }
+    }
+}
+
+impl<Req, Resp> Server<Req, Resp> {
+    // Blocks until a request comes in. None once all of the clients are gone.
+    pub fn receive(&mut self) -> Option<(Req, Responder<Resp>)> {
+        let (request, reply) = self.receiver.receive()?;
+        Some((request, Responder { reply }))
+    }
+
+    pub fn try_receive(&mut self) -> Result<(Req, Responder<Resp>), channels::TryReceiveError> {
+        let (request, reply) = self.receiver.try_receive()?;
+        Ok((request, Responder { reply }))
+    }
+
+    // Every request until all of the clients are gone, so a server is just a for loop.
+    pub fn iter(&mut self) -> Iter<'_, Req, Resp> {
+        Iter { server: self }
+    }
+}
+
+pub struct Iter<'a, Req, Resp> {
+    server: &'a mut Server<Req, Resp>,
+}
+
+impl<Req, Resp> Iterator for Iter<'_, Req, Resp> {
+    type Item = (Req, Responder<Resp>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.server.receive()
+    }
+}
+
+pub struct IntoIter<Req, Resp> {
+    server: Server<Req, Resp>,
+}
+
+impl<Req, Resp> Iterator for IntoIter<Req, Resp> {
+    type Item = (Req, Responder<Resp>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.server.receive()
+    }
+}
+
+impl<Req, Resp> IntoIterator for Server<Req, Resp> {
+    type Item = (Req, Responder<Resp>);
+    type IntoIter = IntoIter<Req, Resp>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter { server: self }
+    }
+}
+
+impl<'a, Req, Resp> IntoIterator for &'a mut Server<Req, Resp> {
+    type Item = (Req, Responder<Resp>);
+    type IntoIter = Iter<'a, Req, Resp>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+// The other half of one call. It's the only way to answer that call, and dropping it without answering is how the client finds out
+// it isn't getting one.
+pub struct Responder<Resp> {
+    reply: oneshot::Sender<Resp>,
+}
+
+impl<Resp> Responder<Resp> {
+    // Takes self cause there's exactly one answer per call. Only fails if the client isn't waiting anymore, which only happens if it panicked.
+    pub fn respond(self, response: Resp) -> Result<(), SendError<Resp>> {
+        self.reply.send(response)
+    }
+}
+
+pub enum CallError<Req> {
+    // There's no server left, the request was never sent. Here it is back.
+    Disconnected(Req),
+    // A server took the request but dropped its Responder without answering.
+    NoResponse,
+}
+
+impl<Req> CallError<Req> {
+    // The request, if it never made it to a server.
+    pub fn into_request(self) -> Option<Req> {
+        match self {
+            CallError::Disconnected(request) => Some(request),
+            CallError::NoResponse => None,
+        }
+    }
+}
+
+// Written by hand for the same reason as SendError's, no Req: Debug needed.
+impl<Req> std::fmt::Debug for CallError<Req> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CallError::Disconnected(_) => f.write_str("Disconnected(..)"),
+            CallError::NoResponse => f.write_str("NoResponse"),
+        }
+    }
+}
+
+impl<Req> std::fmt::Display for CallError<Req> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CallError::Disconnected(_) => f.write_str("calling on a disconnected channel"),
+            CallError::NoResponse => {
+                f.write_str("the server dropped the request without responding")
+            }
+        }
+    }
+}
+
+impl<Req> std::error::Error for CallError<Req> {}
+
+// Its convention to return the sending side first and then the receiving one.
+pub fn rpc_channel<Req, Resp>() -> (Client<Req, Resp>, Server<Req, Resp>) {
+    let (sender, receiver) = channels::channel();
+    (Client { sender }, Server { receiver })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        sync::{Arc, Barrier},
+        thread,
+    };
+
+    #[test]
+    fn call_gets_its_response() {
+        let (mut client, server) = rpc_channel();
+
+        let t = thread::spawn(move || {
+            for (request, responder) in server {
+                responder.respond(request * 2).unwrap();
+            }
+        });
+
+        assert_eq!(client.call(1).unwrap(), 2);
+        assert_eq!(client.call(21).unwrap(), 42);
+        drop(client);
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn every_client_gets_its_own_response() {
+        let (client, mut server) = rpc_channel::<u32, u32>();
+
+        let clients: Vec<_> = (0..4)
+            .map(|id| {
+                let mut client = client.clone();
+                thread::spawn(move || {
+                    for i in 0..100 {
+                        let request = id * 1000 + i;
+                        assert_eq!(client.call(request).unwrap(), request + 1);
+                    }
+                })
+            })
+            .collect();
+        drop(client);
+
+        for (request, responder) in &mut server {
+            responder.respond(request + 1).unwrap();
+        }
+        for t in clients {
+            t.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn cloned_servers_share_the_work() {
+        let (mut client, server) = rpc_channel();
+
+        let workers: Vec<_> = (0..3)
+            .map(|_| {
+                let server = server.clone();
+                thread::spawn(move || {
+                    let mut handled = 0;
+                    for (request, responder) in server {
+                        responder.respond(format!("handled {request}")).unwrap();
+                        handled += 1;
+                    }
+                    handled
+                })
+            })
+            .collect();
+        drop(server);
+
+        for i in 0..30 {
+            assert_eq!(client.call(i).unwrap(), format!("handled {i}"));
+        }
+        drop(client);
+
+        let handled: usize = workers.into_iter().map(|t| t.join().unwrap()).sum();
+        assert_eq!(handled, 30);
+    }
+
+    #[test]
+    fn cloned_servers_handle_calls_in_parallel() {
+        const SERVERS: usize = 4;
+        let (client, server) = rpc_channel::<usize, usize>();
+        // Nobody gets to respond until every server is in the middle of a call. If one server grabbed more than its share,
+        // or they took turns, the barrier would never fill up and nothing would ever come back.
+        let all_busy = Arc::new(Barrier::new(SERVERS));
+
+        let servers: Vec<_> = (0..SERVERS)
+            .map(|id| {
+                let server = server.clone();
+                let all_busy = Arc::clone(&all_busy);
+                thread::spawn(move || {
+                    let mut handled = 0;
+                    for (_, responder) in server {
+                        all_busy.wait();
+                        responder.respond(id).unwrap();
+                        handled += 1;
+                    }
+                    handled
+                })
+            })
+            .collect();
+        drop(server);
+
+        let calls: Vec<_> = (0..SERVERS)
+            .map(|i| {
+                let mut client = client.clone();
+                thread::spawn(move || client.call(i).unwrap())
+            })
+            .collect();
+        drop(client);
+
+        let mut handled_by: Vec<_> = calls.into_iter().map(|t| t.join().unwrap()).collect();
+        handled_by.sort();
+        assert_eq!(handled_by, (0..SERVERS).collect::<Vec<_>>());
+
+        for t in servers {
+            assert_eq!(t.join().unwrap(), 1);
+        }
+    }
+
+    #[test]
+    fn call_without_server() {
+        let (mut client, server) = rpc_channel::<String, ()>();
+        drop(server);
+
+        let error = client.call(String::from("anyone?")).unwrap_err();
+        assert_eq!(error.into_request().as_deref(), Some("anyone?"));
+    }
+
+    #[test]
+    fn dropped_responder_fails_the_call() {
+        let (mut client, mut server) = rpc_channel::<u32, u32>();
+
+        let t = thread::spawn(move || {
+            let (_request, responder) = server.receive().unwrap();
+            drop(responder);
+        });
+
+        assert!(matches!(client.call(1), Err(CallError::NoResponse)));
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn server_ends_once_clients_are_gone() {
+        let (client, mut server) = rpc_channel::<(), ()>();
+        assert!(matches!(
+            server.try_receive(),
+            Err(channels::TryReceiveError::Empty)
+        ));
+
+        drop(client);
+        assert!(server.receive().is_none());
+    }
+}
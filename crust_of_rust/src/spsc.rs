@@ -0,0 +1,275 @@
+// Single producer, single consumer ring buffer. Fixed capacity, and neither side ever waits on the other, try_push on a full buffer and
+// try_pop on an empty one just tell you so. No locks, no CAS loops, every operation is a couple of loads and one store, so it's wait-free.
+//
+// It only works because there is exactly one producer and one consumer. The producer is the only one that writes tail and the consumer is
+// the only one that writes head. That's why Producer and Consumer aren't Clone and their methods take &mut self, the type system
+// won't let you get a second one.
+//
+// The synchronization is the release and acquire pattern from memory_ordering::release_and_acquire over in rust-concurrency. The producer
+// writes the value into the slot and then stores tail with Release, the consumer loads tail with Acquire and from then on is guaranteed
+// to see the value. Same thing the other way around with head, so the producer doesn't overwrite a slot the consumer is still reading.
+
+use std::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    ops::Deref,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+// head and tail are written by different threads all the time. If they sit in the same cache line, every write by one side kicks the line
+// out of the other side's cache even though they never touch each other's index (false sharing). 128 bytes cause some CPUs prefetch cache
+// lines in pairs.
+#[repr(align(128))]
+struct CachePadded<T>(T);
+
+impl<T> Deref for CachePadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+struct Shared<T> {
+    // Index of the next slot to read. Only the consumer writes it.
+    head: CachePadded<AtomicUsize>,
+    // Index of the next slot to write. Only the producer writes it.
+    tail: CachePadded<AtomicUsize>,
+    // The indices count up to twice the capacity and then start over at 0, the slot is the index modulo the capacity.
+    // Going up to twice the capacity is what lets us tell a full buffer (tail is capacity ahead of head) from an empty one (tail == head).
+    buffer: Box<[UnsafeCell<MaybeUninit<T>>]>,
+}
+
+// Each slot is only touched by one side at a time, head and tail decide which one. Values move between threads, hence T: Send.
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+impl<T> Shared<T> {
+    fn slot(&self, index: usize) -> *mut MaybeUninit<T> {
+        self.buffer[index % self.buffer.len()].get()
+    }
+
+    fn next_index(&self, index: usize) -> usize {
+        if index + 1 == 2 * self.buffer.len() {
+            0
+        } else {
+            index + 1
+        }
+    }
+
+    // How many values sit between head and tail.
+    fn len_between(&self, head: usize, tail: usize) -> usize {
+        if tail >= head {
+            tail - head
+        } else {
+            tail + 2 * self.buffer.len() - head
+        }
+    }
+}
+
+impl<T> Drop for Shared<T> {
+    fn drop(&mut self) {
+        // Whatever was pushed but never popped is still in there, and MaybeUninit doesn't drop anything on its own.
+        let head = *self.head.0.get_mut();
+        let tail = *self.tail.0.get_mut();
+        let mut index = head;
+        while index != tail {
+            // SAFETY: Everything from head up to tail has been written and not read, and both sides are gone.
+            unsafe { (*self.slot(index)).assume_init_drop() };
+            index = self.next_index(index);
+        }
+    }
+}
+
+pub struct Producer<T> {
+    shared: Arc<Shared<T>>,
+    // The last head we loaded. The consumer only ever moves head forward, so if this says there's room there definitely is,
+    // and we only need to go look at the real head when it says we're full.
+    cached_head: usize,
+}
+
+impl<T> Producer<T> {
+    // Gives the value back if the buffer is full.
+    pub fn try_push(&mut self, value: T) -> Result<(), T> {
+        // We're the only one writing tail, so we know what it is.
+        let tail = self.shared.tail.load(Ordering::Relaxed);
+
+        if self.shared.len_between(self.cached_head, tail) == self.capacity() {
+            // Acquire pairs with the consumer's Release store, we must not write a slot before the consumer is done reading it.
+            self.cached_head = self.shared.head.load(Ordering::Acquire);
+            if self.shared.len_between(self.cached_head, tail) == self.capacity() {
+                return Err(value);
+            }
+        }
+
+        // SAFETY: The slot is between head and head + capacity, not readable by the consumer until we move tail past it.
+        unsafe { (*self.shared.slot(tail)).write(value) };
+        // Release so the consumer sees the value once it sees the new tail.
+        self.shared
+            .tail
+            .store(self.shared.next_index(tail), Ordering::Release);
+        Ok(())
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.buffer.len()
+    }
+}
+
+pub struct Consumer<T> {
+    shared: Arc<Shared<T>>,
+    // Same trick as cached_head on the producer, tail only moves forward.
+    cached_tail: usize,
+}
+
+impl<T> Consumer<T> {
+    // None if the buffer is empty.
+    pub fn try_pop(&mut self) -> Option<T> {
+        // We're the only one writing head, so we know what it is.
+        let head = self.shared.head.load(Ordering::Relaxed);
+
+        if head == self.cached_tail {
+            // Acquire pairs with the producer's Release store, that's what makes the value in the slot visible to us.
+            self.cached_tail = self.shared.tail.load(Ordering::Acquire);
+            if head == self.cached_tail {
+                return None;
+            }
+        }
+
+        // SAFETY: The slot is between head and tail, the producer wrote it and won't touch it again until we move head past it.
+        let value = unsafe { (*self.shared.slot(head)).assume_init_read() };
+        // Release so the producer only reuses the slot after we're done reading it.
+        self.shared
+            .head
+            .store(self.shared.next_index(head), Ordering::Release);
+        Some(value)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.buffer.len()
+    }
+}
+
+// Its convention to return the sending side first and then the receiving one.
+pub fn channel<T>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+    // Nothing would ever fit.
+    assert!(capacity > 0, "spsc capacity must be at least 1");
+
+    let buffer = (0..capacity)
+        .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+        .collect();
+    let shared = Arc::new(Shared {
+        head: CachePadded(AtomicUsize::new(0)),
+        tail: CachePadded(AtomicUsize::new(0)),
+        buffer,
+    });
+
+    (
+        Producer {
+            shared: Arc::clone(&shared),
+            cached_head: 0,
+        },
+        Consumer {
+            shared,
+            cached_tail: 0,
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::DropCounter;
+
+    #[test]
+    fn push_then_pop() {
+        let (mut producer, mut consumer) = channel(2);
+        assert_eq!(consumer.try_pop(), None);
+
+        producer.try_push(1).unwrap();
+        producer.try_push(2).unwrap();
+        assert_eq!(consumer.try_pop(), Some(1));
+        assert_eq!(consumer.try_pop(), Some(2));
+        assert_eq!(consumer.try_pop(), None);
+    }
+
+    #[test]
+    fn full_buffer_hands_value_back() {
+        let (mut producer, mut consumer) = channel(2);
+        producer.try_push(1).unwrap();
+        producer.try_push(2).unwrap();
+        assert_eq!(producer.try_push(3), Err(3));
+
+        assert_eq!(consumer.try_pop(), Some(1));
+        producer.try_push(3).unwrap();
+        assert_eq!(consumer.try_pop(), Some(2));
+        assert_eq!(consumer.try_pop(), Some(3));
+    }
+
+    #[test]
+    fn wraps_around() {
+        let (mut producer, mut consumer) = channel(3);
+        for i in 0..100 {
+            producer.try_push(i).unwrap();
+            assert_eq!(consumer.try_pop(), Some(i));
+        }
+    }
+
+    #[test]
+    fn head_and_tail_live_on_separate_cache_lines() {
+        assert!(std::mem::align_of::<CachePadded<AtomicUsize>>() >= 128);
+    }
+
+    #[test]
+    fn across_threads() {
+        const COUNT: usize = 100_000;
+        let (mut producer, mut consumer) = channel(16);
+
+        let t = std::thread::spawn(move || {
+            for i in 0..COUNT {
+                let mut value = i;
+                // Nobody waits for us here, retrying is on the caller.
+                while let Err(rejected) = producer.try_push(value) {
+                    value = rejected;
+                    // Yield rather than spin, with fewer cores than threads spinning just burns the time slice the other side needs.
+                    std::thread::yield_now();
+                }
+            }
+        });
+
+        let mut expected = 0;
+        while expected < COUNT {
+            match consumer.try_pop() {
+                Some(value) => {
+                    assert_eq!(value, expected);
+                    expected += 1;
+                }
+                None => std::thread::yield_now(),
+            }
+        }
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn leftover_values_are_dropped() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let (mut producer, mut consumer) = channel(4);
+        for _ in 0..3 {
+            assert!(producer.try_push(DropCounter(drops.clone())).is_ok());
+        }
+        drop(consumer.try_pop());
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+
+        drop(producer);
+        drop(consumer);
+        assert_eq!(drops.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_capacity_panics() {
+        let _ = channel::<()>(0);
+    }
+}
@@ -0,0 +1,15 @@
+// Fixtures shared by the tests of more than one module.
+
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+
+// Counts how many times it was dropped, so we can tell if queued values were dropped, leaked or dropped twice.
+pub(crate) struct DropCounter(pub(crate) Arc<AtomicUsize>);
+
+impl Drop for DropCounter {
+    fn drop(&mut self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
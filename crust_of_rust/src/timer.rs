@@ -0,0 +1,336 @@
+// Timers as channels. after gives you a Receiver that gets a single Instant once the delay is up, tick gives you one that gets an Instant every period.
+// Since they're plain channels::Receivers they go into a Select right next to everything else, that's the whole point. No more
+// thread::sleep loops polling a counter like atomics::progress_reporting over in rust-concurrency does.
+//
+// All of the timers share one thread. It keeps their deadlines in a heap, sleeps on a condvar until the earliest one is due (or a new timer
+// shows up that's due even earlier), sends, and goes back to sleep. The thread is started by the first timer and lives as long as the process.
+
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    sync::{Condvar, Mutex, OnceLock},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    channels::{self, Receiver, Sender, TrySendError},
+    poison::{lock, wait, wait_timeout},
+};
+
+// Gets a single Instant, the moment it fired, once duration has passed. After that the channel is disconnected.
+// A duration too big for an Instant (Duration::MAX, say) never fires, same as a receive_timeout that big waits forever.
+pub fn after(duration: Duration) -> Receiver<Instant> {
+    // Only ever one value in it, so it's never full.
+    let (sender, receiver) = channels::sync_channel(1);
+    timer().schedule(Instant::now().checked_add(duration), None, sender);
+    receiver
+}
+
+// Gets an Instant every period, for as long as the Receiver is around.
+// It holds at most one tick. If the receiver doesn't keep up the extra ticks are dropped instead of piling up, or worse, making the timer thread
+// wait on it and hold up every other timer.
+pub fn tick(period: Duration) -> Receiver<Instant> {
+    // It would never get to do anything else.
+    assert!(!period.is_zero(), "tick period must be greater than zero");
+
+    let (sender, receiver) = channels::sync_channel(1);
+    timer().schedule(Instant::now().checked_add(period), Some(period), sender);
+    receiver
+}
+
+struct Timer {
+    inner: Mutex<Inner>,
+    // Poked whenever a timer is added, the new one might be due before whatever the thread is sleeping towards.
+    signal_scheduled: Condvar,
+}
+
+struct Inner {
+    heap: BinaryHeap<Entry>,
+    next_sequence: u64,
+    // The heap size at which schedule next clears out timers nobody listens to anymore, see prune.
+    prune_at: usize,
+}
+
+impl Inner {
+    // Don't bother pruning a heap smaller than this, a few dead entries are cheaper to keep than to look for.
+    const MIN_PRUNE_AT: usize = 64;
+}
+
+struct Entry {
+    // None is never. The sender is kept around so the receiver doesn't see a disconnect, but it never fires.
+    deadline: Option<Instant>,
+    // Timers with the same deadline fire in the order they were created. Same trick as in priority_channel.
+    sequence: u64,
+    // None for after, it fires once and is done.
+    period: Option<Duration>,
+    sender: Sender<Instant>,
+}
+
+// BinaryHeap is a max heap and we want the earliest deadline on top, so everything is compared the other way around.
+// Option would put None first, never has to come after every actual deadline instead.
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let deadlines = match (other.deadline, self.deadline) {
+            (Some(other), Some(this)) => other.cmp(&this),
+            (None, None) => Ordering::Equal,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(_), None) => Ordering::Less,
+        };
+        deadlines.then_with(|| other.sequence.cmp(&self.sequence))
+    }
+}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Entry {}
+
+// The one timer everybody shares. OnceLock makes sure only the first caller starts the thread, the way lazy_initialization in atomics
+// would if it didn't race.
+fn timer() -> &'static Timer {
+    static TIMER: OnceLock<Timer> = OnceLock::new();
+
+    TIMER.get_or_init(|| {
+        thread::Builder::new()
+            .name(String::from("timer"))
+            .spawn(|| timer().run())
+            .expect("failed to spawn the timer thread");
+
+        Timer {
+            inner: Mutex::new(Inner {
+                heap: BinaryHeap::new(),
+                next_sequence: 0,
+                prune_at: Inner::MIN_PRUNE_AT,
+            }),
+            signal_scheduled: Condvar::new(),
+        }
+    })
+}
+
+impl Timer {
+    fn schedule(
+        &self,
+        deadline: Option<Instant>,
+        period: Option<Duration>,
+        sender: Sender<Instant>,
+    ) {
+        let mut inner = lock(&self.inner);
+        let sequence = inner.next_sequence;
+        inner.next_sequence += 1;
+        inner.heap.push(Entry {
+            deadline,
+            sequence,
+            period,
+            sender,
+        });
+        let pruned = prune(&mut inner);
+        drop(inner);
+        // Dropping a Sender wakes whoever waits on its channel, that's not something to do with the timer's lock held.
+        drop(pruned);
+
+        self.signal_scheduled.notify_one();
+    }
+
+    fn run(&self) {
+        let mut inner = lock(&self.inner);
+
+        loop {
+            let now = Instant::now();
+
+            // Everything that's due comes out in one go. The sends happen without the lock, a send can wake up a receive_async and who knows
+            // what the waker does, it could even want to schedule a timer itself.
+            let mut due = Vec::new();
+            while inner
+                .heap
+                .peek()
+                .is_some_and(|entry| entry.deadline.is_some_and(|deadline| deadline <= now))
+            {
+                due.push(inner.heap.pop().expect("just peeked it"));
+            }
+
+            if !due.is_empty() {
+                drop(inner);
+                let rescheduled: Vec<_> = due
+                    .into_iter()
+                    .filter_map(|entry| fire(entry, now))
+                    .collect();
+                inner = lock(&self.inner);
+                inner.heap.extend(rescheduled);
+                continue;
+            }
+
+            // Like every other condvar wait in here it can wake up early, the loop just checks the heap again.
+            inner = match inner.heap.peek().and_then(|entry| entry.deadline) {
+                Some(deadline) => wait_timeout(&self.signal_scheduled, inner, deadline - now),
+                // Nothing at all, or only timers that never fire.
+                None => wait(&self.signal_scheduled, inner),
+            };
+        }
+    }
+}
+
+// A timer only finds out its receiver is gone when it fires. Until then the entry and its Sender stay in the heap, and a loop that makes an
+// after(Duration::from_secs(30)) timeout on every iteration would pile up thousands of them, never firing ones never go away at all.
+// So once the heap has doubled since the last time, everything with a dropped receiver is taken out. Every entry is looked at once per
+// doubling, which keeps it O(1) per schedule on average. The pruned entries are handed back to be dropped outside the lock.
+fn prune(inner: &mut Inner) -> Vec<Entry> {
+    if inner.heap.len() < inner.prune_at {
+        return Vec::new();
+    }
+
+    let (pruned, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut inner.heap)
+        .into_vec()
+        .into_iter()
+        .partition(|entry| entry.sender.is_disconnected());
+    inner.heap = BinaryHeap::from(kept);
+    inner.prune_at = (inner.heap.len() * 2).max(Inner::MIN_PRUNE_AT);
+    pruned
+}
+
+// Sends the tick and hands the entry back if it wants to fire again.
+fn fire(mut entry: Entry, now: Instant) -> Option<Entry> {
+    match entry.sender.try_send(now) {
+        // Nobody is listening anymore, which is how a tick ends. If prune doesn't get to it first, this is where the timer notices.
+        Err(TrySendError::Disconnected(_)) => return None,
+        // The receiver still hasn't picked up the last tick, this one is dropped.
+        Err(TrySendError::Full(_)) | Ok(()) => {}
+    }
+
+    let period = entry.period?;
+    // Only due entries are fired, they all have a deadline.
+    let mut deadline = entry.deadline.expect("fired a timer that never fires");
+    deadline = match deadline.checked_add(period) {
+        Some(next) => next,
+        // The next tick doesn't fit in an Instant, so there is no next tick.
+        None => {
+            entry.deadline = None;
+            return Some(entry);
+        }
+    };
+    // We fell behind by more than a whole period (the machine was busy, or the process was suspended). Firing a burst of ticks to catch up
+    // is pointless, the receiver only holds one anyways, so we start counting from now.
+    if deadline <= now {
+        deadline = now + period;
+    }
+    entry.deadline = Some(deadline);
+    Some(entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channels::{ReceiveTimeoutError, Select};
+
+    #[test]
+    fn after_fires_once() {
+        let start = Instant::now();
+        let mut receiver = after(Duration::from_millis(50));
+
+        let fired = receiver.receive().unwrap();
+        assert!(fired - start >= Duration::from_millis(50));
+        // It's a one off, the sender is gone after it fired.
+        assert_eq!(receiver.receive(), None);
+    }
+
+    #[test]
+    fn after_does_not_fire_early() {
+        let mut receiver = after(Duration::from_millis(200));
+        assert_eq!(
+            receiver.receive_timeout(Duration::from_millis(20)),
+            Err(ReceiveTimeoutError::Timeout)
+        );
+    }
+
+    #[test]
+    fn earlier_timer_added_later_fires_first() {
+        let mut late = after(Duration::from_millis(300));
+        let mut early = after(Duration::from_millis(30));
+
+        let early_fired = early.receive().unwrap();
+        let late_fired = late.receive().unwrap();
+        assert!(early_fired < late_fired);
+    }
+
+    #[test]
+    fn tick_keeps_firing() {
+        let start = Instant::now();
+        let mut receiver = tick(Duration::from_millis(20));
+
+        let ticks: Vec<_> = receiver.iter().take(3).collect();
+        assert!(ticks.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(ticks[2] - start >= Duration::from_millis(60));
+    }
+
+    #[test]
+    fn slow_tick_receiver_holds_one_tick() {
+        let mut receiver = tick(Duration::from_millis(10));
+        thread::sleep(Duration::from_millis(100));
+
+        // About ten ticks went by but only one of them was kept.
+        assert!(receiver.try_receive().is_ok());
+        assert!(receiver.try_receive().is_err());
+    }
+
+    #[test]
+    fn timeout_in_a_select() {
+        let (_sender, mut work) = channels::channel::<String>();
+        let mut timeout = after(Duration::from_millis(30));
+
+        let mut select = Select::new();
+        let work_index = select.add(&work);
+        let timeout_index = select.add(&timeout);
+
+        // Nothing ever shows up on work, so the timer is what ends the wait.
+        assert_eq!(select.wait(), timeout_index);
+        assert!(timeout.try_receive().is_ok());
+        assert_ne!(work_index, timeout_index);
+        assert!(work.try_receive().is_err());
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_period_panics() {
+        let _ = tick(Duration::ZERO);
+    }
+
+    #[test]
+    fn dropped_timers_dont_pile_up() {
+        for _ in 0..10_000 {
+            drop(after(Duration::from_secs(30)));
+            drop(after(Duration::MAX));
+        }
+
+        // Other tests have their own timers in there too, but nowhere near this many.
+        let len = lock(&timer().inner).heap.len();
+        assert!(len < 1_000, "{len} timers still in the heap");
+    }
+
+    #[test]
+    fn too_far_off_never_fires() {
+        let mut never = after(Duration::MAX);
+        let mut never_ticks = tick(Duration::MAX);
+        // Other timers still work with those in the heap.
+        let mut soon = after(Duration::from_millis(20));
+
+        assert!(soon.receive().is_some());
+        // Not fired and not disconnected either, just waiting.
+        assert_eq!(
+            never.receive_timeout(Duration::from_millis(20)),
+            Err(ReceiveTimeoutError::Timeout)
+        );
+        assert_eq!(
+            never_ticks.receive_timeout(Duration::from_millis(20)),
+            Err(ReceiveTimeoutError::Timeout)
+        );
+    }
+}
@@ -0,0 +1,320 @@
+// A watch channel only ever holds one value, the latest one. Sending overwrites it and receivers don't get a queue of everything that happened,
+// they get told that something changed and then look at whatever is there now. Made for things like live settings where only the newest value matters.
+//
+// Same Mutex + Condvar setup as channels. The value carries a version that goes up by one on every send, and every receiver
+// remembers the version it last saw, that's how changed knows whether there is anything new for it.
+
+use std::{
+    ops::Deref,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+};
+
+use crate::{
+    channels::SendError,
+    poison::{lock, wait},
+};
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+// Same as channels, no #[derive(Clone)] cause Arc is Clone no matter what T is.
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        let mut inner = lock(&self.shared.inner);
+        inner.senders += 1;
+        drop(inner);
+
+        Sender {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut inner = lock(&self.shared.inner);
+        inner.senders -= 1;
+        let is_last_sender = inner.senders == 0;
+        drop(inner);
+
+        // Everybody waiting in changed has to find out that there won't be any more changes.
+        if is_last_sender {
+            self.shared.signal_changed.notify_all();
+        }
+    }
+}
+
+impl<T> Sender<T> {
+    // Replaces the value. Never blocks, there's no queue to fill up. Fails only when nobody is left to watch it.
+    pub fn send(&mut self, value: T) -> Result<(), SendError<T>> {
+        let mut inner = lock(&self.shared.inner);
+        if inner.receivers == 0 {
+            return Err(SendError(value));
+        }
+
+        let old_value = std::mem::replace(&mut inner.value, value);
+        inner.version += 1;
+        drop(inner);
+        // The old value could be anything, no reason to drop it while holding the lock.
+        drop(old_value);
+
+        // Every receiver wants to know, not just one of them.
+        self.shared.signal_changed.notify_all();
+        Ok(())
+    }
+
+    pub fn borrow(&self) -> Ref<'_, T> {
+        Ref {
+            inner: lock(&self.shared.inner),
+        }
+    }
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    // The version of the value this receiver saw last, anything newer counts as a change.
+    seen_version: u64,
+}
+
+// A clone has seen exactly what the original has seen.
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        let mut inner = lock(&self.shared.inner);
+        inner.receivers += 1;
+        drop(inner);
+
+        Receiver {
+            shared: Arc::clone(&self.shared),
+            seen_version: self.seen_version,
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut inner = lock(&self.shared.inner);
+        inner.receivers -= 1;
+    }
+}
+
+impl<T> Receiver<T> {
+    // Blocks until there is a version newer than the one we saw last and marks it as seen. Look at it with borrow afterwards.
+    // If a bunch of sends happen in between you only find out once, and borrow gives you the last of them.
+    // An error means the senders are gone and the value is never going to change again.
+    pub fn changed(&mut self) -> Result<(), ReceiveError> {
+        let mut inner = lock(&self.shared.inner);
+
+        loop {
+            // Checked before the senders so a change made right before the last sender left still counts.
+            if inner.version != self.seen_version {
+                self.seen_version = inner.version;
+                return Ok(());
+            }
+
+            if inner.senders == 0 {
+                return Err(ReceiveError);
+            }
+
+            inner = wait(&self.shared.signal_changed, inner);
+        }
+    }
+
+    // Whether changed would return right away. Doesn't mark anything as seen.
+    pub fn has_changed(&self) -> bool {
+        lock(&self.shared.inner).version != self.seen_version
+    }
+
+    // The current value. It holds the lock while you have it, so don't keep it around, senders have to wait until it's dropped.
+    // Panicking while you hold it poisons the lock, that's fine, the Ref only ever reads, see poison.
+    // Doesn't mark anything as seen either, that's what changed is for.
+    pub fn borrow(&self) -> Ref<'_, T> {
+        Ref {
+            inner: lock(&self.shared.inner),
+        }
+    }
+}
+
+// Like the Ref in ref_cell, only the guard underneath is a MutexGuard.
+pub struct Ref<'a, T> {
+    inner: MutexGuard<'a, Inner<T>>,
+}
+
+impl<T> Deref for Ref<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner.value
+    }
+}
+
+// Returned by changed once all of the senders are gone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReceiveError;
+
+impl std::fmt::Display for ReceiveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("receiving on a disconnected channel")
+    }
+}
+
+impl std::error::Error for ReceiveError {}
+
+struct Inner<T> {
+    value: T,
+    version: u64,
+    senders: usize,
+    receivers: usize,
+}
+
+struct Shared<T> {
+    inner: Mutex<Inner<T>>,
+    signal_changed: Condvar,
+}
+
+// Its convention to return the Sender first and then the Receiver. There's always a value, so you have to start with one.
+// The receiver counts it as seen, changed waits for the first send.
+pub fn channel<T>(initial: T) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        inner: Mutex::new(Inner {
+            value: initial,
+            version: 0,
+            senders: 1,
+            receivers: 1,
+        }),
+        signal_changed: Condvar::new(),
+    });
+
+    (
+        Sender {
+            shared: Arc::clone(&shared),
+        },
+        Receiver {
+            shared,
+            seen_version: 0,
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn borrow_initial_value() {
+        let (_sender, receiver) = channel(String::from("initial"));
+        assert_eq!(*receiver.borrow(), "initial");
+        assert!(!receiver.has_changed());
+    }
+
+    #[test]
+    fn send_overwrites() {
+        let (mut sender, mut receiver) = channel(0);
+        sender.send(1).unwrap();
+        sender.send(2).unwrap();
+
+        // Two sends, one change, and it's the latest value.
+        assert!(receiver.has_changed());
+        receiver.changed().unwrap();
+        assert_eq!(*receiver.borrow(), 2);
+        assert!(!receiver.has_changed());
+    }
+
+    #[test]
+    fn changed_waits_for_a_send() {
+        let (mut sender, mut receiver) = channel(0);
+
+        let t = std::thread::spawn(move || {
+            receiver.changed().unwrap();
+            *receiver.borrow()
+        });
+
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(!t.is_finished());
+        sender.send(5).unwrap();
+        assert_eq!(t.join().unwrap(), 5);
+    }
+
+    #[test]
+    fn every_receiver_sees_the_change() {
+        let (mut sender, receiver) = channel(0);
+
+        let waiting: Vec<_> = (0..4)
+            .map(|_| {
+                let mut receiver = receiver.clone();
+                std::thread::spawn(move || {
+                    receiver.changed().unwrap();
+                    *receiver.borrow()
+                })
+            })
+            .collect();
+
+        std::thread::sleep(Duration::from_millis(50));
+        sender.send(7).unwrap();
+        for t in waiting {
+            assert_eq!(t.join().unwrap(), 7);
+        }
+    }
+
+    #[test]
+    fn clone_keeps_what_was_seen() {
+        let (mut sender, mut receiver) = channel(0);
+        sender.send(1).unwrap();
+        receiver.changed().unwrap();
+
+        let cloned = receiver.clone();
+        assert!(!cloned.has_changed());
+    }
+
+    #[test]
+    fn changed_fails_once_senders_are_gone() {
+        let (mut sender, mut receiver) = channel(0);
+        sender.send(1).unwrap();
+        drop(sender);
+
+        // The last change still gets through, only after that do we hear about the senders.
+        assert_eq!(receiver.changed(), Ok(()));
+        assert_eq!(*receiver.borrow(), 1);
+        assert_eq!(receiver.changed(), Err(ReceiveError));
+    }
+
+    #[test]
+    fn dropping_sender_wakes_up_changed() {
+        let (sender, mut receiver) = channel(0);
+
+        let t = std::thread::spawn(move || receiver.changed());
+
+        std::thread::sleep(Duration::from_millis(50));
+        drop(sender);
+        assert_eq!(t.join().unwrap(), Err(ReceiveError));
+    }
+
+    #[test]
+    fn send_fails_without_receivers() {
+        let (mut sender, receiver) = channel(0);
+        drop(receiver);
+        assert_eq!(sender.send(1), Err(SendError(1)));
+        assert_eq!(*sender.borrow(), 0);
+    }
+
+    #[test]
+    fn panicking_while_borrowed() {
+        let (mut sender, mut receiver) = channel(0);
+        let other = receiver.clone();
+
+        // The Ref poisons the lock on the way out and then the Receiver's drop takes it again, all while unwinding.
+        // That used to panic inside a destructor and abort the whole process.
+        let t = std::thread::spawn(move || {
+            let value = other.borrow();
+            if *value == 0 {
+                panic!("don't like zero");
+            }
+        });
+        assert!(t.join().is_err());
+
+        sender.send(1).unwrap();
+        receiver.changed().unwrap();
+        assert_eq!(*receiver.borrow(), 1);
+    }
+}
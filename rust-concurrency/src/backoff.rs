@@ -0,0 +1,139 @@
+use std::{thread, time::Duration};
+
+// What a spin loop does every time it finds the lock still taken. Every lock() starts with a fresh one from Default, so a strategy can count
+// how long it's been waiting and get more patient the longer it takes.
+//
+// Plain spinning is the fastest way to get a lock that's about to be released, but it's terrible when there are more threads than cores.
+// The thread holding the lock might not even be running, and every spinning thread is burning a core it could have given to it.
+pub trait Backoff: Default {
+    fn snooze(&mut self);
+}
+
+// Just spin_loop, forever. What SpinLock always did, and the right call if the lock is only ever held for a few instructions
+// and every thread has its own core.
+#[derive(Debug, Default)]
+pub struct Spin;
+
+impl Backoff for Spin {
+    fn snooze(&mut self) {
+        std::hint::spin_loop();
+    }
+}
+
+// Spins twice as long every time it's called, up to 2^MAX_STEP spin_loops in a row. Still never gives up the core, but waiting threads
+// stop hammering the lock's cache line, which helps a lot once several of them are fighting over it.
+#[derive(Debug, Default)]
+pub struct Exponential {
+    step: u32,
+}
+
+impl Exponential {
+    const MAX_STEP: u32 = 10;
+}
+
+impl Backoff for Exponential {
+    fn snooze(&mut self) {
+        for _ in 0..1 << self.step {
+            std::hint::spin_loop();
+        }
+        if self.step < Self::MAX_STEP {
+            self.step += 1;
+        }
+    }
+}
+
+// Spins for a bit in case the lock is about to be released, then starts calling yield_now so the thread holding the lock
+// (or anybody else) gets to run. Good enough for most oversubscribed machines.
+#[derive(Debug, Default)]
+pub struct SpinThenYield {
+    spins: u32,
+}
+
+impl SpinThenYield {
+    const SPIN_LIMIT: u32 = 100;
+}
+
+impl Backoff for SpinThenYield {
+    fn snooze(&mut self) {
+        if self.spins < Self::SPIN_LIMIT {
+            self.spins += 1;
+            std::hint::spin_loop();
+        } else {
+            thread::yield_now();
+        }
+    }
+}
+
+// Spins for a bit, then actually goes to sleep. There's nobody to unpark us, the lock doesn't keep track of who's waiting,
+// so it's park_timeout and the timeout doubles every time up to MAX_PARK. A thread waiting on a lock that's held for a long time
+// ends up using next to no CPU, the price is that it can notice the lock was released up to MAX_PARK late.
+#[derive(Debug, Default)]
+pub struct SpinThenPark {
+    spins: u32,
+    park: Duration,
+}
+
+impl SpinThenPark {
+    const SPIN_LIMIT: u32 = 100;
+    const MIN_PARK: Duration = Duration::from_micros(1);
+    const MAX_PARK: Duration = Duration::from_millis(1);
+}
+
+impl Backoff for SpinThenPark {
+    fn snooze(&mut self) {
+        if self.spins < Self::SPIN_LIMIT {
+            self.spins += 1;
+            std::hint::spin_loop();
+            return;
+        }
+
+        self.park = (self.park * 2).clamp(Self::MIN_PARK, Self::MAX_PARK);
+        // Can wake up early, spuriously or cause something else unparked this thread. Either way the lock just gets checked again.
+        thread::park_timeout(self.park);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exponential_doubles_up_to_the_limit() {
+        let mut backoff = Exponential::default();
+        for expected in 1..=Exponential::MAX_STEP {
+            backoff.snooze();
+            assert_eq!(backoff.step, expected);
+        }
+
+        backoff.snooze();
+        assert_eq!(backoff.step, Exponential::MAX_STEP);
+    }
+
+    #[test]
+    fn spin_then_yield_stops_counting_once_it_yields() {
+        let mut backoff = SpinThenYield::default();
+        for _ in 0..SpinThenYield::SPIN_LIMIT + 10 {
+            backoff.snooze();
+        }
+        assert_eq!(backoff.spins, SpinThenYield::SPIN_LIMIT);
+    }
+
+    #[test]
+    fn spin_then_park_parks_longer_and_longer() {
+        let mut backoff = SpinThenPark::default();
+        for _ in 0..SpinThenPark::SPIN_LIMIT {
+            backoff.snooze();
+        }
+        assert_eq!(backoff.park, Duration::ZERO);
+
+        backoff.snooze();
+        assert_eq!(backoff.park, SpinThenPark::MIN_PARK);
+        backoff.snooze();
+        assert_eq!(backoff.park, SpinThenPark::MIN_PARK * 2);
+
+        for _ in 0..20 {
+            backoff.snooze();
+        }
+        assert_eq!(backoff.park, SpinThenPark::MAX_PARK);
+    }
+}
@@ -0,0 +1,10 @@
+pub mod atomics;
+pub mod backoff;
+pub mod interior_mutability;
+pub mod lock;
+pub mod memory_ordering;
+pub mod parking_and_condition_variables;
+pub mod spin_lock;
+pub mod spin_lock_guard;
+pub mod spin_lock_guard_without_lifetime;
+pub mod thread_pool;
@@ -0,0 +1,289 @@
+use std::{
+    cell::UnsafeCell,
+    fmt,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::backoff::{Backoff, Spin};
+
+// The one SpinLock. spin_lock_guard had the Guard but also a safe unlock() that let you unlock while somebody still held a Guard, and
+// spin_lock_guard_without_lifetime had the const fn new. This is both of them plus the bits you'd expect from a lock you actually use.
+//
+// B is what lock() does while it waits, see backoff. It's only a type, a fresh one is made for every lock() call, so it doesn't take up any room.
+pub struct SpinLock<T, B = Spin> {
+    locked: AtomicBool,
+    value: UnsafeCell<T>,
+    // fn() -> B cause the lock never holds a B, so B shouldn't have any say in whether the lock is Send or Sync.
+    backoff: PhantomData<fn() -> B>,
+}
+
+// Sharing the lock only ever hands out the T to one thread at a time, so T: Send is enough, it doesn't have to be Sync. Same as Mutex.
+unsafe impl<T: Send, B> Sync for SpinLock<T, B> {}
+
+// new is only there for the default Spin. Type defaults don't take part in inference, so with new on every B
+// a plain SpinLock::new(0) wouldn't know which B it is. Same reason HashMap::new only exists for RandomState.
+impl<T> SpinLock<T> {
+    // const so it can go straight into a static.
+    pub const fn new(value: T) -> Self {
+        SpinLock::with_backoff(value)
+    }
+}
+
+impl<T, B: Backoff> SpinLock<T, B> {
+    // SpinLock::<_, SpinThenYield>::with_backoff(value), or let the type annotation pick B.
+    pub const fn with_backoff(value: T) -> Self {
+        SpinLock {
+            locked: AtomicBool::new(false),
+            value: UnsafeCell::new(value),
+            backoff: PhantomData,
+        }
+    }
+
+    pub fn lock(&self) -> Guard<'_, T, B> {
+        let mut backoff = B::default();
+        while self.locked.swap(true, Ordering::Acquire) {
+            // Only try the swap again once it looks unlocked. Hammering swap keeps pulling the cache line over to this core in exclusive
+            // mode, a load can share it with everybody else that's waiting.
+            while self.locked.load(Ordering::Relaxed) {
+                backoff.snooze();
+            }
+        }
+
+        Guard { lock: self }
+    }
+
+    // None if somebody else holds it right now, doesn't wait.
+    pub fn try_lock(&self) -> Option<Guard<'_, T, B>> {
+        if self.locked.swap(true, Ordering::Acquire) {
+            None
+        } else {
+            Some(Guard { lock: self })
+        }
+    }
+
+    // The &mut self already proves nobody else can be holding the lock, so there's no locking at all.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+
+    /// Unlocks without going through a Guard, for when the Guard was mem::forget'ed (or the lock was taken over FFI or some such).
+    ///
+    /// # Safety
+    ///
+    /// The lock has to be held and whoever holds it must not touch the value anymore. Every &T and &mut T that came out of the Guard
+    /// has to be gone, and if the Guard is still around it can't be used or dropped after this. Otherwise two threads get at the value at once.
+    pub unsafe fn force_unlock(&self) {
+        self.locked.store(false, Ordering::Release);
+    }
+}
+
+impl<T: Default, B: Backoff> Default for SpinLock<T, B> {
+    fn default() -> Self {
+        SpinLock::with_backoff(T::default())
+    }
+}
+
+// Same as Mutex's Debug, it only peeks at the value if it can get the lock without waiting. Waiting in a Debug impl is a good way
+// to deadlock while printing the lock you're holding.
+impl<T: fmt::Debug, B: Backoff> fmt::Debug for SpinLock<T, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("SpinLock");
+        match self.try_lock() {
+            Some(guard) => debug.field("value", &&*guard),
+            None => debug.field("value", &format_args!("<locked>")),
+        };
+        debug.finish_non_exhaustive()
+    }
+}
+
+// The lifetime gaurantees that the Guard does not outlive the SpinLock.
+pub struct Guard<'a, T, B = Spin> {
+    lock: &'a SpinLock<T, B>,
+}
+
+// A &Guard hands out &T, so sharing the Guard between threads needs T: Sync. Without this it would only need T: Send, like the lock.
+unsafe impl<T: Sync, B> Sync for Guard<'_, T, B> {}
+
+impl<T, B> Deref for Guard<'_, T, B> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Safety: The very existence of this Guard guarantees we've exclusively locked the lock.
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T, B> DerefMut for Guard<'_, T, B> {
+    fn deref_mut(&mut self) -> &mut T {
+        // Safety: The very existence of this Guard guarantees we've exclusively locked the lock.
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T: fmt::Debug, B> fmt::Debug for Guard<'_, T, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+// The drop of course makes sure the lock is released when the lifetime of the Guard ends.
+impl<T, B> Drop for Guard<'_, T, B> {
+    fn drop(&mut self) {
+        self.lock.locked.store(false, Ordering::Release);
+    }
+}
+
+// Everything in here has to run under Miri as well (cargo +nightly miri test spin_lock), that's where a wrong Ordering or an aliasing
+// &mut actually shows up. Miri is slow, so the loops are a lot shorter there.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backoff::{Exponential, SpinThenPark, SpinThenYield};
+    use std::{sync::Arc, thread};
+
+    const ITERATIONS: usize = if cfg!(miri) { 50 } else { 1_000 };
+
+    #[test]
+    fn lock_and_unlock() {
+        let lock = SpinLock::new(1);
+        *lock.lock() += 1;
+        assert_eq!(*lock.lock(), 2);
+    }
+
+    #[test]
+    fn try_lock_fails_while_locked() {
+        let lock = SpinLock::new(());
+        let guard = lock.try_lock().unwrap();
+        assert!(lock.try_lock().is_none());
+
+        drop(guard);
+        assert!(lock.try_lock().is_some());
+    }
+
+    #[test]
+    fn works_in_a_static() {
+        static COUNTER: SpinLock<u32> = SpinLock::new(0);
+        *COUNTER.lock() += 1;
+        assert!(*COUNTER.lock() >= 1);
+    }
+
+    #[test]
+    fn get_mut_and_into_inner() {
+        let mut lock = SpinLock::new(vec![1]);
+        lock.get_mut().push(2);
+        assert_eq!(lock.into_inner(), vec![1, 2]);
+    }
+
+    #[test]
+    fn force_unlock_after_forgetting_the_guard() {
+        let lock = SpinLock::new(5);
+        std::mem::forget(lock.lock());
+        assert!(lock.try_lock().is_none());
+
+        // Safety: The Guard was forgotten, nothing can get at the value through it anymore.
+        unsafe { lock.force_unlock() };
+        assert_eq!(*lock.try_lock().unwrap(), 5);
+    }
+
+    #[test]
+    fn debug_and_default() {
+        let lock = SpinLock::<Vec<u8>>::default();
+        assert_eq!(format!("{lock:?}"), "SpinLock { value: [], .. }");
+
+        let guard = lock.lock();
+        assert_eq!(format!("{guard:?}"), "[]");
+        assert_eq!(format!("{lock:?}"), "SpinLock { value: <locked>, .. }");
+    }
+
+    #[test]
+    fn threads_take_turns() {
+        let lock = SpinLock::new(Vec::new());
+
+        thread::scope(|s| {
+            for id in 0..4 {
+                let lock = &lock;
+                s.spawn(move || {
+                    for i in 0..ITERATIONS {
+                        lock.lock().push((id, i));
+                    }
+                });
+            }
+        });
+
+        let values = lock.into_inner();
+        assert_eq!(values.len(), 4 * ITERATIONS);
+        // Every thread's own pushes are still in the order it did them.
+        for id in 0..4 {
+            let mine: Vec<_> = values
+                .iter()
+                .filter(|(who, _)| *who == id)
+                .map(|(_, i)| *i)
+                .collect();
+            assert_eq!(mine, (0..ITERATIONS).collect::<Vec<_>>());
+        }
+    }
+
+    // Not an atomic in sight besides the lock's own. If the Release in Guard::drop or the Acquire in lock were any weaker,
+    // Miri would call this a data race. Every backoff goes through it, they only change how the waiting is done.
+    fn count_with<B: Backoff + 'static>() {
+        let lock = Arc::new(SpinLock::<usize, B>::with_backoff(0));
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let lock = Arc::clone(&lock);
+                thread::spawn(move || {
+                    for _ in 0..ITERATIONS {
+                        *lock.lock() += 1;
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(*lock.lock(), 4 * ITERATIONS);
+    }
+
+    #[test]
+    fn unlock_publishes_the_writes() {
+        count_with::<Spin>();
+    }
+
+    #[test]
+    fn exponential_backoff() {
+        count_with::<Exponential>();
+    }
+
+    #[test]
+    fn spin_then_yield_backoff() {
+        count_with::<SpinThenYield>();
+    }
+
+    #[test]
+    fn spin_then_park_backoff() {
+        count_with::<SpinThenPark>();
+    }
+
+    #[test]
+    fn parked_waiter_gets_the_lock() {
+        let lock = SpinLock::<_, SpinThenPark>::with_backoff(0);
+
+        thread::scope(|s| {
+            let mut guard = lock.lock();
+            // Held long enough that the waiter is done spinning and parks a few times.
+            let waiter = s.spawn(|| *lock.lock());
+            thread::sleep(std::time::Duration::from_millis(20));
+            *guard = 1;
+            drop(guard);
+
+            assert_eq!(waiter.join().unwrap(), 1);
+        });
+    }
+}
@@ -1,71 +1,9 @@
-use std::{
-    cell::UnsafeCell,
-    ops::{Deref, DerefMut},
-    sync::atomic::{AtomicBool, Ordering},
-    thread,
-};
+use std::thread;
 
-use crate::spin_lock_guard;
-
-// The lifetime gaurantees that the Guard does not outlive the SpinLock.
-pub struct Guard<'a, T> {
-    lock: &'a SpinLock<T>,
-}
-
-impl<T> Deref for Guard<'_, T> {
-    type Target = T;
-
-    fn deref(&self) -> &T {
-        // Direct return is safe because the existence of the Guard itself guarantees the the lock is held.
-        unsafe { &*self.lock.value.get() }
-    }
-}
-
-impl<T> DerefMut for Guard<'_, T> {
-    fn deref_mut(&mut self) -> &mut T {
-        // Direct return is safe because the existence of the Guard itself guarantees the the lock is held.
-        unsafe { &mut *self.lock.value.get() }
-    }
-}
-
-// The drop of course makes sure the lock is released when the lifetime of the lock ends.
-impl<T> Drop for Guard<'_, T> {
-    fn drop(&mut self) {
-        self.lock.locked.store(false, Ordering::Release);
-    }
-}
-
-// The easiest implementation is using a bool and using std::hint::spin_loop().
-// hint::spin_loop() because it hints the processor of the spin lock scenario allowing it to make optimizations as required.
-pub struct SpinLock<T> {
-    locked: AtomicBool,
-    value: UnsafeCell<T>,
-}
-
-unsafe impl<T: Send> Sync for SpinLock<T> {}
-
-impl<T> SpinLock<T> {
-    pub fn new(value: T) -> Self {
-        SpinLock {
-            locked: AtomicBool::new(false),
-            value: UnsafeCell::new(value),
-        }
-    }
-
-    pub fn lock<'a>(&'a self) -> Guard<'a, T> {
-        while self.locked.swap(true, Ordering::Acquire) {
-            std::hint::spin_loop();
-        }
-
-        Guard { lock: self }
-    }
-
-    /// Safety: The &mut T from lock() must be dropped before unlock() is called.
-    /// Make sure not to keep references to any field of T either. It might result in undefined behaviour, and believe me you're not up for that.
-    pub fn unlock(&self) {
-        self.locked.store(false, Ordering::Release);
-    }
-}
+// The SpinLock and Guard that used to be in here live in spin_lock now, together with the one from spin_lock_guard_without_lifetime.
+// The unlock() that was here is gone, it was safe to call while a Guard was still around, which let two threads into the value at once.
+// spin_lock::SpinLock::force_unlock is the unsafe replacement.
+pub use crate::spin_lock::{Guard, SpinLock};
 
 pub fn spin_lock_test() {
     let spin_lock = std::sync::Arc::new(SpinLock::new(Vec::new()));
@@ -1,59 +1,5 @@
-use std::cell::UnsafeCell;
-use std::ops::{Deref, DerefMut};
-use std::sync::atomic::AtomicBool;
-use std::sync::atomic::Ordering::{Acquire, Release};
-
-pub struct SpinLock<T> {
-    locked: AtomicBool,
-    value: UnsafeCell<T>,
-}
-
-unsafe impl<T> Sync for SpinLock<T> where T: Send {}
-
-pub struct Guard<'a, T> {
-    lock: &'a SpinLock<T>,
-}
-
-unsafe impl<T> Sync for Guard<'_, T> where T: Sync {}
-
-impl<T> SpinLock<T> {
-    pub const fn new(value: T) -> Self {
-        Self {
-            locked: AtomicBool::new(false),
-            value: UnsafeCell::new(value),
-        }
-    }
-
-    pub fn lock(&self) -> Guard<T> {
-        while self.locked.swap(true, Acquire) {
-            std::hint::spin_loop();
-        }
-        Guard { lock: self }
-    }
-}
-
-impl<T> Deref for Guard<'_, T> {
-    type Target = T;
-    fn deref(&self) -> &T {
-        // Safety: The very existence of this Guard
-        // guarantees we've exclusively locked the lock.
-        unsafe { &*self.lock.value.get() }
-    }
-}
-
-impl<T> DerefMut for Guard<'_, T> {
-    fn deref_mut(&mut self) -> &mut T {
-        // Safety: The very existence of this Guard
-        // guarantees we've exclusively locked the lock.
-        unsafe { &mut *self.lock.value.get() }
-    }
-}
-
-impl<T> Drop for Guard<'_, T> {
-    fn drop(&mut self) {
-        self.lock.locked.store(false, Release);
-    }
-}
+// The SpinLock and Guard that used to be in here live in spin_lock now, const fn new and all.
+pub use crate::spin_lock::{Guard, SpinLock};
 
 pub fn spin_lock_without_lifetime() {
     use std::thread;
@@ -0,0 +1,350 @@
+// A fixed number of worker threads that run whatever jobs you hand them. It's the thread::scope + AtomicUsize setup from
+// atomics::multiple_thread_progress_report, just done once and for all: the jobs go through a crust_of_rust channel, every worker holds
+// a clone of the receiver, and whichever worker is free picks up the next job. The progress counters are atomics like num_done over there.
+//
+// Every job gets a JobHandle back that you can wait on for its result. A job that panics only takes itself down, the panic is caught,
+// the worker moves on to the next job and the handle hands you the panic instead of a result.
+
+use std::{
+    any::Any,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread::{self, JoinHandle},
+};
+
+use crust_of_rust::{channels, oneshot};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+pub struct ThreadPool {
+    // None once we've started shutting down. Dropping it is what tells the workers that no more jobs are coming.
+    sender: Option<channels::Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+    counters: Arc<Counters>,
+}
+
+// Relaxed all around, these are only ever read to tell somebody how far along we are. The results themselves go through the oneshots.
+#[derive(Default)]
+struct Counters {
+    submitted: AtomicUsize,
+    completed: AtomicUsize,
+    panicked: AtomicUsize,
+}
+
+impl ThreadPool {
+    // Up to 16 jobs per worker can be waiting before execute starts blocking.
+    pub fn new(workers: usize) -> Self {
+        Self::with_queue_capacity(workers, workers * 16)
+    }
+
+    // The queue is a bounded channel on purpose, a producer that's faster than the workers gets slowed down instead of piling up jobs forever.
+    // Every worker takes one job at a time off it either way, cloned receivers never grab more than that.
+    pub fn with_queue_capacity(workers: usize, queue_capacity: usize) -> Self {
+        assert!(workers > 0, "a thread pool needs at least one worker");
+        assert!(
+            queue_capacity > 0,
+            "the job queue needs room for at least one job"
+        );
+
+        let (sender, receiver) = channels::sync_channel::<Job>(queue_capacity);
+        let counters = Arc::new(Counters::default());
+
+        let workers = (0..workers)
+            .map(|id| {
+                let mut receiver = receiver.clone();
+                thread::Builder::new()
+                    .name(format!("pool-worker-{id}"))
+                    .spawn(move || {
+                        // Ends once the pool has let go of the sender and the queue is empty, so every job that got in still runs.
+                        while let Some(job) = receiver.receive() {
+                            job();
+                        }
+                    })
+                    .expect("failed to spawn a worker thread")
+            })
+            .collect();
+
+        ThreadPool {
+            sender: Some(sender),
+            workers,
+            counters,
+        }
+    }
+
+    // Queues the job and hands back a handle for its result. Blocks while the queue is full.
+    pub fn execute<F, R>(&mut self, job: F) -> JobHandle<R>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (result_sender, result_receiver) = oneshot::channel();
+        let counters = Arc::clone(&self.counters);
+
+        let job: Job = Box::new(move || {
+            // The job isn't the only user code in here. If the handle is gone, sending hands the result back and it's dropped right here,
+            // and R's drop can panic just as well. So sending goes under a catch_unwind too, a worker never runs anything that isn't.
+            let handed_back = panic::catch_unwind(AssertUnwindSafe(move || {
+                // AssertUnwindSafe cause we never look at anything the job touched after it panicked, all we do is pass the panic along.
+                let result = panic::catch_unwind(AssertUnwindSafe(job));
+                if result.is_err() {
+                    counters.panicked.fetch_add(1, Ordering::Relaxed);
+                }
+                counters.completed.fetch_add(1, Ordering::Relaxed);
+                // Nobody wanting the result is fine, the handle might have been dropped.
+                let _ = result_sender.send(result);
+            }));
+            // Nobody to hand this one to, the handle is gone. Dropping the payload is more user code that could panic, so it's leaked instead.
+            if let Err(payload) = handed_back {
+                std::mem::forget(payload);
+            }
+        });
+
+        self.counters.submitted.fetch_add(1, Ordering::Relaxed);
+        self.sender
+            .as_mut()
+            .expect("the sender is only taken out when the pool goes away")
+            .send(job)
+            .expect("workers only stop after the sender is gone");
+
+        JobHandle {
+            receiver: result_receiver,
+        }
+    }
+
+    pub fn worker_count(&self) -> usize {
+        self.workers.len()
+    }
+
+    // A snapshot of the counters, it's a little out of date by the time you look at it with the workers still going.
+    pub fn progress(&self) -> Progress {
+        let queued = self.sender.as_ref().map_or(0, |sender| sender.len());
+        Progress {
+            submitted: self.counters.submitted.load(Ordering::Relaxed),
+            completed: self.counters.completed.load(Ordering::Relaxed),
+            panicked: self.counters.panicked.load(Ordering::Relaxed),
+            queued,
+        }
+    }
+
+    // Graceful shutdown. No new jobs, everything already queued still runs, and this waits for all of it and for the workers to exit.
+    pub fn join(mut self) -> Progress {
+        self.shut_down();
+        self.progress()
+    }
+
+    fn shut_down(&mut self) {
+        drop(self.sender.take());
+        for worker in self.workers.drain(..) {
+            // Jobs can't take a worker down, everything they run is under a catch_unwind, so this doesn't fail. If it ever did, panicking
+            // here would be worse, this runs in Drop, quite possibly while some other panic is already unwinding.
+            let _ = worker.join();
+        }
+    }
+}
+
+// Dropping the pool is the same as join, it doesn't leave jobs or threads behind.
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        self.shut_down();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    pub submitted: usize,
+    // Panicked ones included.
+    pub completed: usize,
+    pub panicked: usize,
+    // Submitted but not picked up by a worker yet.
+    pub queued: usize,
+}
+
+pub struct JobHandle<R> {
+    receiver: oneshot::Receiver<thread::Result<R>>,
+}
+
+impl<R> JobHandle<R> {
+    // Blocks until the job has run.
+    pub fn join(self) -> Result<R, JobError> {
+        match self.receiver.receive() {
+            Some(Ok(result)) => Ok(result),
+            Some(Err(payload)) => Err(JobError::Panicked(payload)),
+            None => Err(JobError::Dropped),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum JobError {
+    // The job panicked, this is what it panicked with. Hand it to panic::resume_unwind to carry on panicking in this thread.
+    Panicked(Box<dyn Any + Send + 'static>),
+    // The job was dropped without ever running. The pool never does that, it runs everything it took, but the type system doesn't know that.
+    Dropped,
+}
+
+impl std::fmt::Display for JobError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JobError::Panicked(_) => f.write_str("the job panicked"),
+            JobError::Dropped => f.write_str("the job was dropped before it ran"),
+        }
+    }
+}
+
+impl std::error::Error for JobError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::Mutex, time::Duration};
+
+    #[test]
+    fn results_come_back_on_their_handle() {
+        let mut pool = ThreadPool::new(4);
+        let handles: Vec<_> = (0..20).map(|i| pool.execute(move || i * i)).collect();
+
+        let results: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(results, (0..20).map(|i| i * i).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn panicking_job_does_not_take_down_the_pool() {
+        let mut pool = ThreadPool::new(1);
+
+        let bad = pool.execute(|| -> u32 { panic!("job blew up") });
+        let good = pool.execute(|| 7);
+
+        match bad.join() {
+            Err(JobError::Panicked(payload)) => {
+                assert_eq!(payload.downcast_ref::<&str>(), Some(&"job blew up"));
+            }
+            other => panic!("expected a panic, got {other:?}"),
+        }
+        // Same single worker, still alive.
+        assert_eq!(good.join().unwrap(), 7);
+
+        let progress = pool.join();
+        assert_eq!(progress.completed, 2);
+        assert_eq!(progress.panicked, 1);
+    }
+
+    #[test]
+    fn join_runs_everything_that_was_queued() {
+        let done = Arc::new(AtomicUsize::new(0));
+        let mut pool = ThreadPool::new(2);
+
+        for _ in 0..10 {
+            let done = Arc::clone(&done);
+            // Dropping the handle doesn't cancel anything.
+            drop(pool.execute(move || {
+                thread::sleep(Duration::from_millis(5));
+                done.fetch_add(1, Ordering::Relaxed);
+            }));
+        }
+
+        let progress = pool.join();
+        assert_eq!(done.load(Ordering::Relaxed), 10);
+        assert_eq!(
+            progress,
+            Progress {
+                submitted: 10,
+                completed: 10,
+                panicked: 0,
+                queued: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn jobs_run_on_every_worker() {
+        let mut pool = ThreadPool::new(3);
+        assert_eq!(pool.worker_count(), 3);
+        let names = Arc::new(Mutex::new(Vec::new()));
+
+        let handles: Vec<_> = (0..3)
+            .map(|_| {
+                let names = Arc::clone(&names);
+                pool.execute(move || {
+                    names
+                        .lock()
+                        .unwrap()
+                        .push(thread::current().name().unwrap().to_owned());
+                    // Long enough that one worker can't get to all of them.
+                    thread::sleep(Duration::from_millis(100));
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let mut names = names.lock().unwrap().clone();
+        names.sort();
+        assert_eq!(names, ["pool-worker-0", "pool-worker-1", "pool-worker-2"]);
+    }
+
+    #[test]
+    fn progress_while_running() {
+        let mut pool = ThreadPool::with_queue_capacity(1, 4);
+        let (mut gate_sender, gate_receiver) = channels::channel::<()>();
+        let gate = Arc::new(Mutex::new(gate_receiver));
+
+        // The first job holds up the only worker until we open the gate, the rest have to wait in the queue.
+        let handles: Vec<_> = (0..3)
+            .map(|_| {
+                let gate = Arc::clone(&gate);
+                pool.execute(move || {
+                    gate.lock().unwrap().receive();
+                })
+            })
+            .collect();
+
+        thread::sleep(Duration::from_millis(50));
+        let progress = pool.progress();
+        assert_eq!(progress.submitted, 3);
+        assert_eq!(progress.completed, 0);
+        assert_eq!(progress.queued, 2);
+
+        for _ in 0..3 {
+            gate_sender.send(()).unwrap();
+        }
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(pool.progress().completed, 3);
+    }
+
+    #[test]
+    fn panicking_result_drop_does_not_take_down_the_pool() {
+        struct PanicOnDrop;
+
+        impl Drop for PanicOnDrop {
+            fn drop(&mut self) {
+                panic!("result blew up on drop");
+            }
+        }
+
+        let mut pool = ThreadPool::new(1);
+        let (mut gate_sender, mut gate) = channels::channel::<()>();
+
+        // The handle is gone before the job finishes, so the result comes back to the worker and gets dropped over there.
+        drop(pool.execute(move || {
+            gate.receive();
+            PanicOnDrop
+        }));
+        gate_sender.send(()).unwrap();
+
+        // Same single worker, still alive.
+        assert_eq!(pool.execute(|| 7).join().unwrap(), 7);
+        assert_eq!(pool.join().completed, 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_workers_panics() {
+        let _ = ThreadPool::new(0);
+    }
+}